@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use chess::{Board, ChessMove};
+use log::error;
+
+use crate::analysis_store::AnalysisStore;
+use crate::uci::PossibleMove;
+
+/// The result of a finished search for a single position
+#[derive(Clone, Debug)]
+pub struct CachedAnalysis {
+    pub depth: u8,                  // the shallowest depth across all the lines
+    pub lines: Vec<PossibleMove>,   // one entry per MultiPV line, best first
+    pub best_move: ChessMove
+}
+
+#[cfg(test)]
+impl CachedAnalysis {
+    /// A search of the starting position with two lines: 1. e4 e5, and a promotion to check moves are kept whole
+    pub fn for_test(depth :u8, score :i32) -> Self {
+        use chess::{Piece, Square};
+
+        let moves = vec![ChessMove::new(Square::E2, Square::E4, None), ChessMove::new(Square::E7, Square::E5, None)];
+
+        CachedAnalysis {
+            depth,
+            lines: vec![
+                PossibleMove { depth, score, multi_pv: 1, moves: moves.clone(), ..PossibleMove::default() },
+                PossibleMove { depth, score: -score, multi_pv: 2, moves: vec![ChessMove::new(Square::A7, Square::A8, Some(Piece::Queen))], ..PossibleMove::default() }
+            ],
+            best_move: moves[0]
+        }
+    }
+}
+
+/// In-memory cache of finished searches
+/// Entries are keyed by the board (which hashes with its Zobrist hash) and the number of MultiPV lines,
+/// a deeper result is always returned for a shallower request
+/// When a store is attached, misses fall through to it and finished searches are saved to it
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisCache {
+    entries: HashMap<(Board, u16), CachedAnalysis>,
+    store: Option<AnalysisStore>
+}
+
+impl AnalysisCache {
+    pub fn new() -> Self {
+        AnalysisCache::default()
+    }
+
+    pub fn set_store(&mut self, store :AnalysisStore) {
+        self.store = Some(store);
+    }
+
+    /// Looks up a search of at least `depth` for the board with the given number of MultiPV lines
+    pub fn get(&mut self, board :&Board, multi_pv :u16, depth :u8) -> Option<CachedAnalysis> {
+        let key = (*board, multi_pv);
+
+        if let Some(analysis) = self.entries.get(&key).filter(|analysis| analysis.depth >= depth) {
+            return Some(analysis.clone());
+        }
+
+        // check the store from previous sessions, keeping what we find in memory
+        let analysis = self.store.as_ref()?.get(board, multi_pv, depth)?.clone();
+
+        self.entries.insert(key, analysis.clone());
+
+        Some(analysis)
+    }
+
+    /// Saves a finished search, only replacing an existing entry if this one is at least as deep
+    pub fn insert(&mut self, board :&Board, multi_pv :u16, analysis :CachedAnalysis) {
+        // nothing useful to save without a line
+        if analysis.lines.is_empty() {
+            return;
+        }
+
+        let key = (*board, multi_pv);
+
+        if let Some(existing) = self.entries.get(&key) {
+            if existing.depth > analysis.depth {
+                return;
+            }
+        }
+
+        if let Some(store) = self.store.as_mut() {
+            if let Err(e) = store.insert(board, multi_pv, analysis.clone()) {
+                error!("Error saving analysis: {:?}", e);
+            }
+        }
+
+        self.entries.insert(key, analysis);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chess::Board;
+
+    use crate::analysis_cache::{AnalysisCache, CachedAnalysis};
+
+    #[test]
+    fn deeper_result_for_shallower_request() {
+        let mut cache = AnalysisCache::new();
+        let board = Board::default();
+
+        cache.insert(&board, 1, CachedAnalysis::for_test(10, 35));
+
+        assert_eq!(10, cache.get(&board, 1, 5).unwrap().depth);
+        assert_eq!(10, cache.get(&board, 1, 10).unwrap().depth);
+        assert!(cache.get(&board, 1, 11).is_none());
+    }
+
+    #[test]
+    fn keyed_by_multi_pv() {
+        let mut cache = AnalysisCache::new();
+        let board = Board::default();
+
+        cache.insert(&board, 1, CachedAnalysis::for_test(10, 35));
+
+        assert!(cache.get(&board, 5, 5).is_none());
+        assert!(cache.get(&board.null_move().unwrap(), 1, 5).is_none());
+    }
+
+    #[test]
+    fn keeps_deepest_result() {
+        let mut cache = AnalysisCache::new();
+        let board = Board::default();
+
+        cache.insert(&board, 1, CachedAnalysis::for_test(10, 35));
+        cache.insert(&board, 1, CachedAnalysis::for_test(5, 80));
+
+        assert_eq!(35, cache.get(&board, 1, 1).unwrap().lines[0].score);
+
+        cache.insert(&board, 1, CachedAnalysis::for_test(12, 20));
+
+        assert_eq!(20, cache.get(&board, 1, 1).unwrap().lines[0].score);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chess::{Board, ChessMove};
+use itertools::Itertools;
+use log::{debug, warn};
+
+use crate::analysis_cache::CachedAnalysis;
+use crate::uci::PossibleMove;
+
+/// Finished searches saved to a local file, so they're available in later sessions
+/// Every line in the file is one search, with tab separated fields:
+/// engine, position (FEN without the move counters), MultiPV, depth, best move, lines
+/// The lines are separated by ';' and each one is: multi_pv depth score pv...
+/// Records are only ever appended, when loading the deepest one for a position wins
+#[derive(Clone, Debug)]
+pub struct AnalysisStore {
+    path: PathBuf,
+    engine: String,
+    entries: HashMap<(String, u16), CachedAnalysis>
+}
+
+impl AnalysisStore {
+    /// Opens the store at the given path, loading everything saved for the engine
+    /// The file is created on the first insert
+    pub fn open(path :&Path, engine :&str) -> io::Result<Self> {
+        let mut store = AnalysisStore {
+            path: path.to_path_buf(),
+            engine: engine.replace('\t', " "),
+            entries: HashMap::new()
+        };
+
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+
+                match Self::parse_record(&line) {
+                    Some((engine, position, multi_pv, analysis)) => {
+                        if engine == store.engine {
+                            store.keep_deepest(position, multi_pv, analysis);
+                        }
+                    },
+                    None => warn!("Skipping bad analysis record: {}", line)
+                }
+            }
+        }
+
+        debug!("Loaded {} positions for {} from {}", store.entries.len(), store.engine, path.display());
+
+        Ok(store)
+    }
+
+    /// Looks up a search of at least `depth` for the board with the given number of MultiPV lines
+    pub fn get(&self, board :&Board, multi_pv :u16, depth :u8) -> Option<&CachedAnalysis> {
+        self.entries
+            .get(&(position_key(board), multi_pv))
+            .filter(|analysis| analysis.depth >= depth)
+    }
+
+    /// Saves a finished search to the file if it's deeper than what we already have
+    pub fn insert(&mut self, board :&Board, multi_pv :u16, analysis :CachedAnalysis) -> io::Result<()> {
+        let position = position_key(board);
+
+        if let Some(existing) = self.entries.get(&(position.clone(), multi_pv)) {
+            if existing.depth >= analysis.depth {
+                return Ok(());
+            }
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+
+        writeln!(file, "{}", Self::format_record(&self.engine, &position, multi_pv, &analysis))?;
+
+        self.entries.insert((position, multi_pv), analysis);
+
+        Ok(())
+    }
+
+    fn keep_deepest(&mut self, position :String, multi_pv :u16, analysis :CachedAnalysis) {
+        let key = (position, multi_pv);
+
+        if let Some(existing) = self.entries.get(&key) {
+            if existing.depth >= analysis.depth {
+                return;
+            }
+        }
+
+        self.entries.insert(key, analysis);
+    }
+
+    fn format_record(engine :&str, position :&str, multi_pv :u16, analysis :&CachedAnalysis) -> String {
+        let lines = analysis.lines.iter().map(|pm| {
+            format!("{} {} {} {}", pm.multi_pv, pm.depth, pm.score, pm.moves.iter().join(" "))
+        }).join(";");
+
+        format!("{}\t{}\t{}\t{}\t{}\t{}", engine, position, multi_pv, analysis.depth, analysis.best_move, lines)
+    }
+
+    fn parse_record(record :&str) -> Option<(String, String, u16, CachedAnalysis)> {
+        let fields = record.split('\t').collect_vec();
+
+        if fields.len() != 6 {
+            return None;
+        }
+
+        let mut lines = Vec::new();
+
+        for line in fields[5].split(';') {
+            let mut tokens = line.split_whitespace();
+
+            let multi_pv = tokens.next()?.parse().ok()?;
+            let depth = tokens.next()?.parse().ok()?;
+            let score = tokens.next()?.parse().ok()?;
+            let moves = tokens.map(|mv| ChessMove::from_str(mv).ok()).collect::<Option<Vec<_>>>()?;
+
+            lines.push(PossibleMove { depth, score, multi_pv, moves, ..PossibleMove::default() });
+        }
+
+        let analysis = CachedAnalysis {
+            depth: fields[3].parse().ok()?,
+            lines,
+            best_move: ChessMove::from_str(fields[4]).ok()?
+        };
+
+        Some((fields[0].to_string(), fields[1].to_string(), fields[2].parse().ok()?, analysis))
+    }
+}
+
+/// The FEN without the halfmove clock and fullmove number, so transpositions share a key
+fn position_key(board :&Board) -> String {
+    board.to_string().split_whitespace().take(4).join(" ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use chess::Board;
+
+    use crate::analysis_cache::CachedAnalysis;
+    use crate::analysis_store::AnalysisStore;
+
+    fn temp_path(name :&str) -> PathBuf {
+        let path = env::temp_dir().join(format!("cgir_{}_{}.db", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn reopen_store() {
+        let path = temp_path("reopen");
+        let board = Board::default();
+
+        let mut store = AnalysisStore::open(&path, "Stockfish 13").unwrap();
+        store.insert(&board, 2, CachedAnalysis::for_test(12, 35)).unwrap();
+
+        let store = AnalysisStore::open(&path, "Stockfish 13").unwrap();
+        let analysis = store.get(&board, 2, 10).unwrap();
+
+        assert_eq!(12, analysis.depth);
+        assert_eq!(35, analysis.lines[0].score);
+        assert_eq!(2, analysis.lines[0].moves.len());
+        assert_eq!(Some(chess::Piece::Queen), analysis.lines[1].moves[0].get_promotion());
+        assert!(store.get(&board, 2, 13).is_none());
+        assert!(store.get(&board, 1, 10).is_none());
+
+        // other engines don't see each other's analysis
+        let store = AnalysisStore::open(&path, "Ethereal").unwrap();
+        assert!(store.get(&board, 2, 1).is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn deepest_record_wins() {
+        let path = temp_path("deepest");
+        let board = Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/3P1P2/PPP3PP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let same_position = Board::from_str("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/3P1P2/PPP3PP/RNBQKBNR w KQkq - 4 9").unwrap();
+
+        let mut store = AnalysisStore::open(&path, "Stockfish 13").unwrap();
+        store.insert(&board, 1, CachedAnalysis::for_test(8, 10)).unwrap();
+        store.insert(&board, 1, CachedAnalysis::for_test(15, 20)).unwrap();
+        store.insert(&board, 1, CachedAnalysis::for_test(5, 30)).unwrap();
+
+        let store = AnalysisStore::open(&path, "Stockfish 13").unwrap();
+
+        assert_eq!(20, store.get(&same_position, 1, 1).unwrap().lines[0].score);
+        assert_eq!(2, fs::read_to_string(&path).unwrap().lines().count());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs;
+use std::io;
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chess::Game;
+use log::error;
+
+use crate::config;
+use crate::uci::{Uci, Analysis};
+
+pub const DEFAULT_DEPTH :u8 = 13;
+
+const HASH_SIZES :[usize; 3] = [16, 64, 256];   // in MB
+
+/// Middlegame & endgame positions from stockfish's bench, so results are comparable
+const POSITIONS :[&str; 8] = [
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/8 b - - 0 1",
+];
+
+/// The numbers from running every position with one Threads/Hash setting
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub threads: usize,
+    pub hash: usize,
+    pub nodes: u64,
+    pub time: Duration,                 // total time-to-depth
+    pub position_times: Vec<Duration>   // time-to-depth for each position
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.time.as_secs_f64().max(0.001)) as u64
+    }
+}
+
+/// Runs all the positions to the given depth through a freshly started engine
+pub fn run(engine_path :&str, depth :u8, threads :usize, hash :usize) -> BenchResult {
+    let mut uci = Uci::start_engine(&mut Command::new(engine_path));
+
+    uci.set_option("Threads", &threads.to_string());
+    uci.set_option("Hash", &hash.to_string());
+
+    let mut result = BenchResult { threads, hash, nodes: 0, time: Duration::default(), position_times: Vec::new() };
+
+    for fen in POSITIONS.iter() {
+        let game = Game::from_str(fen).expect("Bad bench position");
+
+        // start each position with an empty hash table
+        uci.new_game();
+
+        let start = Instant::now();
+        let mut nodes = 0;
+
+        for analysis in uci.analyze(&game, vec![], Some(depth)) {
+            if let Analysis::PossibleMove(pm) = analysis {
+                nodes = nodes.max(pm.nodes);
+            }
+        }
+
+        let time = start.elapsed();
+
+        result.nodes += nodes;
+        result.time += time;
+        result.position_times.push(time);
+    }
+
+    result
+}
+
+/// The settings with the shortest total time-to-depth
+pub fn fastest(results :&[BenchResult]) -> Option<&BenchResult> {
+    results.iter().min_by_key(|result| result.time)
+}
+
+/// The Threads & Hash settings picked the last time the benchmark was run
+pub fn recommended_settings() -> Option<(usize, usize)> {
+    let contents = fs::read_to_string(config::data_dir().join("bench.txt")).ok()?;
+    let mut values = contents.split_whitespace().map(|value| value.parse::<usize>());
+
+    match (values.next(), values.next()) {
+        (Some(Ok(threads)), Some(Ok(hash))) => Some((threads, hash)),
+        _ => None
+    }
+}
+
+fn save_recommended_settings(result :&BenchResult) -> io::Result<()> {
+    fs::write(config::data_dir().join("bench.txt"), format!("{} {}\n", result.threads, result.hash))
+}
+
+/// Benchmarks the engine across Threads/Hash settings, prints the numbers,
+/// and saves the fastest settings for starting engines from then on
+pub fn run_command(engine_path :&str, depth :u8) {
+    let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+
+    // powers of 2, and all the cores
+    let mut thread_counts = (0..).map(|exp| 1 << exp).take_while(|threads| *threads < cores).collect::<Vec<usize>>();
+    thread_counts.push(cores);
+
+    println!("Benchmarking {} to depth {} on {} cores", engine_path, depth, cores);
+    println!("{:>8} {:>8} {:>14} {:>12} {:>12}", "Threads", "Hash", "Nodes", "Time (ms)", "NPS");
+
+    let mut results = Vec::new();
+
+    for threads in thread_counts.iter() {
+        for hash in HASH_SIZES.iter() {
+            let result = run(engine_path, depth, *threads, *hash);
+
+            println!("{:>8} {:>8} {:>14} {:>12} {:>12}", result.threads, result.hash, result.nodes, result.time.as_millis(), result.nps());
+            println!("{:>8} time-to-depth (ms): {:?}", "", result.position_times.iter().map(|time| time.as_millis()).collect::<Vec<_>>());
+
+            results.push(result);
+        }
+    }
+
+    if let Some(best) = fastest(&results) {
+        println!("Fastest: Threads {} Hash {}", best.threads, best.hash);
+
+        if let Err(e) = save_recommended_settings(best) {
+            error!("Error saving the benchmark results: {:?}", e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bench::{fastest, BenchResult};
+
+    #[test]
+    fn fastest_time_to_depth() {
+        let make_result = |threads, millis| BenchResult {
+            threads,
+            hash: 16,
+            nodes: 1_000_000,
+            time: Duration::from_millis(millis),
+            position_times: vec![]
+        };
+
+        let results = vec![make_result(1, 4000), make_result(2, 2200), make_result(4, 2500)];
+
+        assert_eq!(2, fastest(&results).unwrap().threads);
+        assert_eq!(250_000, results[0].nps());
+        assert!(fastest(&[]).is_none());
+    }
+}
@@ -1,7 +1,7 @@
-use druid::{Widget, EventCtx, LifeCycle, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, Point, Rect, Color, Affine, MouseEvent, TextLayout, Selector, Target, KbKey};
+use druid::{Widget, EventCtx, LifeCycle, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, Point, Rect, Vec2, Color, Affine, MouseEvent, TextLayout, Selector, Target, KbKey};
 use druid::RenderContext;
 use druid::widget::{SvgData, Label};
-use druid::kurbo::Circle;
+use druid::kurbo::{Circle, BezPath};
 
 use crate::State;
 use std::fs::File;
@@ -10,50 +10,100 @@ use std::io::prelude::*;
 
 use log::{debug, error};
 use itertools::rev;
-use chess::{Square, Piece, Board, ChessMove, MoveGen, BitBoard, Game};
-use crate::uci::{Uci, Analysis};
-use std::process::Command;
+use chess::{Square, Piece, Board, ChessMove, MoveGen, BitBoard};
+use crate::uci::Analysis;
+use crate::config;
+use crate::tablebase::Tablebase;
+use crate::consensus::Consensus;
+use crate::game_tree::NodeId;
+use crate::game_over;
+use crate::draws::{self, DrawReason};
 use std::collections::HashSet;
 use std::thread;
+use std::time::Instant;
+use std::sync::Arc;
 
 
 const BROWN :Color = Color::rgb8(0x91, 0x67, 0x2c);
 const WHITE :Color = Color::WHITE;
 const HIGHLIGHT :Color = Color::AQUA;
 const GREEN :Color = Color::GREEN;
+const HINT_COLOR :Color = Color::rgba8(0x00, 0x80, 0x00, 0xa0);
+const THREAT_COLOR :Color = Color::rgba8(0xc0, 0x00, 0x00, 0xa0);
+
+const THREAT_PLIES :usize = 3;  // how much of the threat line is drawn
 
-const ENGINE_DEPTH :u8 = 3;     // how deep should the engine we're playing against look
 const ANALYSIS_DEPTH :u8 = 5;   // how deep should the analysis engine look?
 
+/// Asks the board for a hint in the current position
+pub const HINT :Selector = Selector::new("hint");
+
+/// The analysis engine's suggestion: the position it was asked for, and the move
+pub const HINT_MOVE :Selector<(Board, ChessMove)> = Selector::new("hint-move");
+
+/// What the opponent would play if it were their move: the position it was searched for, and the line
+pub const THREAT :Selector<(Board, Vec<ChessMove>)> = Selector::new("threat");
+
+/// Every analysis engine's lines for a position, once they've all finished
+pub const CONSENSUS :Selector<Consensus> = Selector::new("consensus");
+
+/// The opponent's move: the request it answers, the node it's played from, and the move
+pub const BEST_MOVE :Selector<(usize, NodeId, ChessMove)> = Selector::new("best_move");
+
+/// The opponent's answer to a draw offer: the node it was offered after, and if it's accepted
+pub const DRAW_OFFER :Selector<(NodeId, bool)> = Selector::new("draw-offer");
+
+/// The opponent's decision on its turn: the request it answers, the node it decided at, and the draw it claims, or None to play on
+pub const DRAW_CLAIM :Selector<(usize, NodeId, Option<DrawReason>)> = Selector::new("draw-claim");
+
+/// Takes back the human's last move, and the opponent's reply if it's been made
+pub const TAKEBACK :Selector = Selector::new("takeback");
+
+/// Deletes the move on the board and everything after it, when no game is being played
+pub const DELETE_MOVE :Selector = Selector::new("delete-move");
+
+/// Puts the position after a move in the game tree on the board
+pub const SELECT_NODE :Selector<NodeId> = Selector::new("select-node");
+
+/// The analysis engine's suggestion for a position
+struct Hint {
+    position: Board,    // the hint goes away once the position changes
+    best_move: ChessMove,
+    show_arrow: bool    // first only the piece is highlighted, then the whole move
+}
+
 
 pub struct BoardWidget {
-    analysis_uci: Uci,   // keep the analysis with the widget
+    tablebase: Option<Tablebase>,   // endgame tables, for the exact result once few enough pieces are left
     square_size: f64,
     white_bottom: bool, // is white on the bottom of the board?
     mouse_down: Option<MouseEvent>, // we deal with mouse events on the _up_ or _move_, so just record this
     selected_square: Option<Square>,
     dragging_piece: Option<(Square, Point)>,  // square on the board being dragged & it's current position
-    pieces_being_attacked: HashSet<Square>
+    pieces_being_attacked: HashSet<Square>,
+    hint: Option<Hint>,
+    threat: Option<(Board, Vec<ChessMove>)>,  // what the opponent would play if it were their move, for a position
+    threat_search: Option<Board>    // the position the threat is being searched for
 }
 
 impl BoardWidget {
     pub(crate) fn new() -> Self {
-        // setup the stockfish engine
-        let mut stockfish_cmd = Command::new("/usr/games/stockfish");
-        let mut analysis_engine = Uci::start_engine(&mut stockfish_cmd);
-
-        // set a few options for analysis
-        analysis_engine.set_option("UCI_AnalyseMode", "true");
-        analysis_engine.set_option("MultiPV", "5");
+        let tablebase = match Tablebase::open(&config::data_dir().join("syzygy")) {
+            Ok(tablebase) => Some(tablebase),
+            Err(e) => { debug!("No tablebases: {:?}", e); None }
+        };
 
         BoardWidget {
-            analysis_uci: Uci::start_engine(&mut stockfish_cmd),
+            tablebase,
             square_size: 0.0,
             white_bottom: true,
             mouse_down: None,
             selected_square: None,
             dragging_piece: None,
-            pieces_being_attacked: HashSet::new()
+            pieces_being_attacked: HashSet::new(),
+            hint: None,
+            threat: None,
+            threat_search: None
         }
     }
 
@@ -113,6 +163,181 @@ impl BoardWidget {
             None
         }
     }
+
+    /// Shows the tablebase result in the analysis area, or clears it if the position isn't covered
+    fn update_tablebase(&mut self, data: &mut State) {
+        let board = data.game.current_position();
+
+        let result = match self.tablebase.as_ref() {
+            Some(tablebase) => tablebase.probe(&board, draws::halfmove_clock(&data.game, data.game.current())),
+            None => None
+        };
+
+        data.analysis = result.map(|result| result.describe(&board, data.notation)).unwrap_or_default();
+    }
+
+    /// Catches up with another position from the game being shown, dropping anything half-done on the old one
+    fn navigated(&mut self, data: &mut State) {
+        data.consensus = None;
+        self.update_tablebase(data);
+
+        self.mouse_down = None;
+        self.selected_square = None;
+        self.dragging_piece = None;
+    }
+
+    /// The first hint for a position highlights the piece to move, asking again shows the whole move
+    /// The analysis engine searches in the background, the move comes back as a HINT_MOVE command
+    fn show_hint(&mut self, ctx: &mut EventCtx, data: &mut State) {
+        let board = data.game.current_position();
+
+        if let Some(hint) = self.hint.as_mut() {
+            if hint.position == board {
+                hint.show_arrow = true;
+                return;
+            }
+        }
+
+        // nothing to suggest once the game is over, or while the opponent is thinking
+        if MoveGen::new_legal(&board).len() == 0 || data.engines.analysis_paused() {
+            return;
+        }
+
+        let mut uci = data.engines.analysis();
+        let game = data.game.to_game();
+        let event_sink = ctx.get_external_handle();
+
+        thread::spawn(move || {
+            let best_move = uci.analyze(&game, vec![], Some(ANALYSIS_DEPTH)).iter().find_map(|analysis| {
+                match analysis {
+                    Analysis::BestMove(best_move) => Some(best_move),
+                    _ => None
+                }
+            });
+
+            if let Some(best_move) = best_move {
+                if let Err(e) = event_sink.submit_command(HINT_MOVE, Box::new((board, best_move)), Target::Global) {
+                    error!("Error submitting hint: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Searches for the opponent's threat in the position in the background, unless we already have it
+    /// The line comes back as a THREAT command
+    fn update_threat(&mut self, ctx: &mut UpdateCtx, board :Board, data :&State) {
+        if self.threat.as_ref().map_or(false, |(position, _line)| *position == board) || self.threat_search == Some(board) {
+            return;
+        }
+
+        // the old threat is for another position
+        self.threat = None;
+
+        // wait for the opponent's move, the position is about to change anyway
+        if data.engines.analysis_paused() {
+            return;
+        }
+
+        let mut uci = data.engines.analysis();
+        let game = data.game.to_game();
+        let event_sink = ctx.get_external_handle();
+
+        self.threat_search = Some(board);
+
+        thread::spawn(move || {
+            // keep the latest line, the one from the final depth
+            let line = uci.threat(&game, ANALYSIS_DEPTH).and_then(|rx| {
+                rx.iter().fold(None, |line, analysis| {
+                    match analysis {
+                        Analysis::PossibleMove(pm) if pm.multi_pv == 1 && !pm.moves.is_empty() => Some(pm.moves),
+                        _ => line
+                    }
+                })
+            });
+
+            debug!("THREAT: {:?}", line);
+
+            if let Err(e) = event_sink.submit_command(THREAT, Box::new((board, line.unwrap_or_default())), Target::Global) {
+                error!("Error submitting threat: {:?}", e);
+            }
+        });
+    }
+
+    /// Asks the opponent for its reply to the move at `node`, from the book or the engine
+    /// The reply comes back as a BEST_MOVE command, tagged with the request so it can be ignored if the move is taken back
+    fn play_opponent(&mut self, ctx: &mut EventCtx, data: &mut State, node :NodeId) {
+        // check the opening book before asking the engine to think
+        let book_move = data.book.as_ref()
+            .filter(|_| data.game.path(node).len() < 2 * data.max_book_depth)
+            .and_then(|book| book.choose(&data.game.node(node).board, data.book_selection));
+
+        let request = data.engines.request_opponent_move();
+
+        if let Some(book_move) = book_move {
+            debug!("BOOK MOVE: {}", book_move);
+            ctx.submit_command(BEST_MOVE.with((request, node, book_move)));
+            return;
+        }
+
+        // start the computer's analysis
+        // on the clock the engine manages its own time, still limited to its level's depth
+        let game = data.game.to_game_at(node);
+        let depth = data.engine_difficulty.depth;
+
+        let rx = match data.clock.as_ref() {
+            Some(clock) => {
+                let now = Instant::now();
+                let (white_time, black_time) = (clock.remaining(chess::Color::White, now), clock.remaining(chess::Color::Black, now));
+
+                data.engines.opponent().play_on_clock(&game, depth, white_time, black_time, clock.control.uci_increment())
+            },
+            None => data.engines.opponent().play(&game, depth, data.engine_difficulty.move_time)
+        };
+        let event_sink = ctx.get_external_handle();
+        let engines = data.engines.clone();
+
+        // spawn a thread to report back when the move has been made
+        thread::spawn(move || {
+            for analysis in rx.iter() {
+                // if we get the best move, then send it as an event
+                if let Analysis::BestMove(best_move) = analysis {
+                    if let Err(e) = event_sink.submit_command(BEST_MOVE, Box::new((request, node, best_move)), Target::Global) {
+                        error!("Error submitting best-move: {:?}", e);
+                    }
+                }
+            }
+
+            // the search is over, even if no move came back (ex: the engine went away), so analysis can carry on
+            if engines.is_current_request(request) {
+                engines.set_opponent_thinking(false);
+            }
+        });
+    }
+
+    /// An arrow from the center of one square to the center of another
+    fn arrow(&self, from :&Square, to :&Square) -> BezPath {
+        let start = self.square2rect(from).center();
+        let end = self.square2rect(to).center();
+
+        let dir = (end - start).normalize();
+        let perp = Vec2::new(-dir.y, dir.x);
+
+        let shaft = self.square_size * 0.08;     // half the width of the shaft
+        let head = self.square_size * 0.35;      // length of the head, and half its width
+        let neck = end - dir * head;
+
+        let mut path = BezPath::new();
+        path.move_to(start + perp * shaft);
+        path.line_to(neck + perp * shaft);
+        path.line_to(neck + perp * head);
+        path.line_to(end);
+        path.line_to(neck - perp * head);
+        path.line_to(neck - perp * shaft);
+        path.line_to(start - perp * shaft);
+        path.close_path();
+
+        path
+    }
 }
 
 impl Widget<State> for BoardWidget {
@@ -120,6 +345,24 @@ impl Widget<State> for BoardWidget {
         // debug!("Board::event: {:?}", event);
 
         match event {
+            Event::KeyDown(key_event) => {
+                // step through the history of the game
+                let current = data.game.current();
+
+                match key_event.key {
+                    KbKey::ArrowLeft => { data.game.back(); },
+                    KbKey::ArrowRight => { data.game.forward(); },
+                    KbKey::Home => data.game.select(data.game.root()),
+                    KbKey::End => data.game.to_end(),
+                    _ => return
+                }
+
+                if data.game.current() != current {
+                    self.navigated(data);
+                }
+
+                ctx.set_handled();
+            },
             Event::KeyUp(key_event) => {
                 // debug!("KEY UP: {:?}", key_event.key);
 
@@ -139,6 +382,15 @@ impl Widget<State> for BoardWidget {
                     panic!("No corresponding MouseDown event");
                 }
 
+                // no more moves once someone resigns or a draw is agreed
+                if data.ending.is_some() {
+                    self.mouse_down = None;
+                    self.selected_square = None;
+                    self.dragging_piece = None;
+                    ctx.request_paint();
+                    return;
+                }
+
                 // convert both the down & up to squares
                 let down_square = self.point2square(&self.mouse_down.as_ref().unwrap().pos);
                 let up_square = self.point2square(&mouse_event.pos);
@@ -241,12 +493,32 @@ impl Widget<State> for BoardWidget {
                 if let Some(mv) = chess_move {
                     // check to see if a blunder was made
                     // we only start checking after 6 moves... cannot screw up that badly that early :-)
-                    if data.disallow_blunders && data.game.actions().len() > 5 {
-                        // get the best move from the analysis engine
-                        let (is_blunder, best_moves) = self.analysis_uci.check_for_blunder(&data.game, mv, ANALYSIS_DEPTH);
+                    if data.disallow_blunders && data.game.ply() > 5 {
+                        // in the endgame the tablebase knows the exact result, so use it instead of searching
+                        let board = data.game.current_position();
+                        let tablebase_blunder = match self.tablebase.as_ref() {
+                            Some(tablebase) => tablebase.is_blunder(&board, draws::halfmove_clock(&data.game, data.game.current()), mv),
+                            None => None
+                        };
+
+                        let is_blunder = if let Some(is_blunder) = tablebase_blunder {
+                            if is_blunder {
+                                debug!("BLUNDER! TABLEBASE YOURS: {}", mv);
+                            }
+
+                            is_blunder
+                        } else {
+                            // get the best move from the analysis engine
+                            let (is_blunder, best_moves) = data.engines.analysis().check_for_blunder(&data.game.to_game(), mv, ANALYSIS_DEPTH);
+
+                            if is_blunder {
+                                debug!("BLUNDER! BEST: {} YOURS: {}", best_moves[0].1, mv);
+                            }
+
+                            is_blunder
+                        };
 
                         if is_blunder {
-                            println!("BLUNDER! BEST: {} YOURS: {}", best_moves[0].1, mv);
                             // unset the chess move
                             chess_move = None;
                         }
@@ -255,27 +527,37 @@ impl Widget<State> for BoardWidget {
 
                 // have to double-check that we have a move, because we might have un-made if there was a blunder
                 if let Some(mv) = chess_move {
-                    // make the move in the game
-                    data.game.make_move(mv);
+                    // make the move in the game, either branching off or replacing the moves after this position
+                    if data.overwrite_history {
+                        data.game.truncate();
+                    }
 
-                    // start the computer's analysis
-                    let rx = data.engine.analyze(&data.game, vec![], Some(ENGINE_DEPTH));
-                    let event_sink = ctx.get_external_handle();
-
-                    // spawn a thread to report back when the move has been made
-                    thread::spawn(move || {
-                        for analysis in rx.iter() {
-                            // if we get the best move, then send it as an event
-                            if let Analysis::BestMove(best_move) = analysis {
-                                if let Err(e) = event_sink.submit_command(Selector::<ChessMove>::new("best_move"), Box::new(best_move), Target::Global) {
-                                    error!("Error submitting best-move: {:?}", e);
-                                }
-                            }
-                        }
-                    });
+                    data.game.make_move(mv);
+                    data.press_clock();
+                    data.consensus = None;
+                    self.update_tablebase(data);
 
                     // make sure nothing is selected
                     self.selected_square = None;
+
+                    // the opponent doesn't get a turn if the game is over
+                    data.check_for_draw(data.game.current());
+
+                    if data.result().is_some() {
+                        game_over::show(ctx, data);
+                        return;
+                    }
+
+                    // it might take a draw instead of moving, the request covers its decision too
+                    let node = data.game.current();
+
+                    match data.claimable_draw() {
+                        Some(reason) => {
+                            let request = data.engines.request_opponent_move();
+                            data.consider_claiming_draw(request, reason, ctx.get_external_handle());
+                        },
+                        None => self.play_opponent(ctx, data, node)
+                    }
                 }
             }, // end of MouseUp match
             Event::MouseMove(mouse_event) => {
@@ -312,15 +594,116 @@ impl Widget<State> for BoardWidget {
             },
             Event::Command(cmd) => {
                 // check to see if we got a best move from the computer
-                if let Some(best_move) = cmd.get(Selector::<ChessMove>::new("best_move")) {
-                    // make the move
-                    data.game.make_move(*best_move);
+                if let Some((request, node, best_move)) = cmd.get(BEST_MOVE) {
+                    // skip replies to moves that were taken back
+                    if !data.engines.is_current_request(*request) {
+                        debug!("STALE BEST MOVE: {}", best_move);
+                        ctx.set_handled();
+                        return;
+                    }
+
+                    data.engines.set_opponent_thinking(false);
+
+                    // the reply goes after the move it answers, even if the board has moved on since
+                    let reply = data.game.add_move(*node, *best_move);
+                    data.press_clock();
+
+                    if data.game.current() == *node {
+                        data.game.select(reply);
+                        self.update_tablebase(data);
+                    }
+
+                    // the reply can end the game even while an earlier position is on the board
+                    data.check_for_draw(reply);
+
+                    if data.ending.is_none() {
+                        data.ending = data.game.result_at(reply);
+                    }
+
+                    if data.result().is_some() {
+                        game_over::show(ctx, data);
+                    }
 
                     // request an update
                     ctx.request_update();
 
                     // mark the event as handled
                     ctx.set_handled();
+                } else if let Some(consensus) = cmd.get(CONSENSUS) {
+                    // the game might have moved on while the engines were searching
+                    if consensus.board == data.game.current_position() {
+                        data.consensus = Some(Arc::new(consensus.clone()));
+                    }
+
+                    ctx.set_handled();
+                } else if let Some((node, accepted)) = cmd.get(DRAW_OFFER) {
+                    if data.draw_offer_answered(*node, *accepted) {
+                        game_over::show(ctx, data);
+                    }
+
+                    ctx.set_handled();
+                } else if let Some((request, node, claim)) = cmd.get(DRAW_CLAIM) {
+                    // skip decisions on moves that were taken back
+                    if data.engines.is_current_request(*request) {
+                        match claim {
+                            Some(reason) => {
+                                data.opponent_claims_draw(*reason);
+                                game_over::show(ctx, data);
+                            },
+                            None => self.play_opponent(ctx, data, *node)
+                        }
+                    }
+
+                    ctx.set_handled();
+                } else if let Some(id) = cmd.get(SELECT_NODE) {
+                    if *id != data.game.current() {
+                        data.game.select(*id);
+                        self.navigated(data);
+                    }
+
+                    ctx.set_handled();
+                } else if cmd.is(TAKEBACK) {
+                    if data.take_back() {
+                        self.navigated(data);
+                    }
+
+                    ctx.set_handled();
+                } else if cmd.is(DELETE_MOVE) {
+                    if data.delete_move() {
+                        self.navigated(data);
+                    }
+
+                    ctx.set_handled();
+                } else if cmd.is(HINT) {
+                    self.show_hint(ctx, data);
+
+                    ctx.request_paint();
+                    ctx.set_handled();
+                } else if let Some((board, best_move)) = cmd.get(HINT_MOVE) {
+                    // only count the hint if it's still for the position on the board, and isn't one we already have
+                    let shown = self.hint.as_ref().map_or(false, |hint| hint.position == *board);
+
+                    if *board == data.game.current_position() && !shown {
+                        debug!("HINT: {}", best_move);
+
+                        self.hint = Some(Hint { position: *board, best_move: *best_move, show_arrow: false });
+                        data.hints_used += 1;
+                    }
+
+                    ctx.request_paint();
+                    ctx.set_handled();
+                } else if let Some((board, line)) = cmd.get(THREAT) {
+                    if self.threat_search == Some(*board) {
+                        self.threat_search = None;
+                    }
+
+                    // the board might have moved on, or the threat been turned off, while the engine searched
+                    if data.show_threat && *board == data.game.current_position() {
+                        self.threat = Some((*board, line.clone()));
+                    }
+
+                    ctx.request_paint();
+                    ctx.set_handled();
                 }
             }
             _ => { }
@@ -382,6 +765,18 @@ impl Widget<State> for BoardWidget {
             }
         }
 
+        // a hint is only good for the position it was given in
+        if self.hint.as_ref().map_or(false, |hint| hint.position != board) {
+            self.hint = None;
+        }
+
+        // search for the threat when it's shown, and forget it otherwise
+        if data.show_threat {
+            self.update_threat(ctx, board, data);
+        } else {
+            self.threat = None;
+        }
+
         // just always request a paint
         ctx.request_paint();
     }
@@ -510,6 +905,35 @@ impl Widget<State> for BoardWidget {
                 });
             }
         }
+
+        // show the start of the threat line, fading as it goes deeper
+        if let Some((_position, line)) = self.threat.as_ref().filter(|(position, _line)| *position == data.game.current_position()) {
+            for (ply, mv) in line.iter().take(THREAT_PLIES).enumerate() {
+                let arrow = self.arrow(&mv.get_source(), &mv.get_dest());
+                let color = THREAT_COLOR.with_alpha(0.6 / (ply + 1) as f64);
+
+                ctx.paint_with_z_index(4, move |ctx| {
+                    ctx.fill(arrow, &color);
+                });
+            }
+        }
+
+        // show the hint, on top of the pieces once it's an arrow
+        if let Some(hint) = self.hint.as_ref() {
+            if hint.show_arrow {
+                let arrow = self.arrow(&hint.best_move.get_source(), &hint.best_move.get_dest());
+
+                ctx.paint_with_z_index(4, move |ctx| {
+                    ctx.fill(arrow, &HINT_COLOR);
+                });
+            } else {
+                let rect = self.square2rect(&hint.best_move.get_source());
+
+                ctx.paint_with_z_index(1, move |ctx| {
+                    ctx.fill(rect, &HINT_COLOR);
+                });
+            }
+        }
     }
 
     fn type_name(&self) -> &'static str {
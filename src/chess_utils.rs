@@ -1,96 +1,285 @@
-use chess::{ChessMove, Board, Color, MoveGen, BitBoard, Square, Piece, BoardStatus};
-use itertools::Itertools;
-use std::collections::HashSet;
+use std::fmt;
 
-pub fn to_notation(chess_move :&ChessMove, board :&Board) -> String {
-    println!("{} -> {}", chess_move.get_source(), chess_move.get_dest());
+use chess::{ChessMove, Board, Color, MoveGen, BitBoard, Piece, BoardStatus, File, Rank, Square};
 
-    let piece = board.piece_on(chess_move.get_source());
+/// Why text couldn't be read as a move
+#[derive(Clone, Debug, PartialEq)]
+pub enum NotationError {
+    Invalid(String),    // not written like a move
+    Illegal(String),    // no legal move matches
+    Ambiguous(String, Vec<ChessMove>)   // more than one legal move matches
+}
 
-    // this is probably an error, so just return the source->destination
-    if piece.is_none() {
-        return format!("{}", chess_move);
+impl fmt::Display for NotationError {
+    fn fmt(&self, f :&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::Invalid(text) => write!(f, "'{}' is not a move", text),
+            NotationError::Illegal(text) => write!(f, "'{}' is not a legal move", text),
+            NotationError::Ambiguous(text, moves) => write!(f, "'{}' could be any of: {}", text, moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>().join(", "))
+        }
     }
+}
+
+impl std::error::Error for NotationError {}
+
+/// Languages for localized piece letters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch
+}
 
-    // check for a stalemate, that's a draw
-    if board.status() == BoardStatus::Stalemate {
-        return "(=)".to_string();
+impl Language {
+    /// King, Queen, Rook, Bishop, Knight
+    fn letters(&self) -> [&'static str; 5] {
+        match self {
+            Language::German => ["K", "D", "T", "L", "S"],
+            Language::French => ["R", "D", "T", "F", "C"],
+            Language::Spanish => ["R", "D", "T", "A", "C"],
+            Language::Italian => ["R", "D", "T", "A", "C"],
+            Language::Dutch => ["K", "D", "T", "L", "P"]
+        }
     }
+}
 
-    let piece = piece.unwrap();
-    let dest = chess_move.get_dest().to_string();
+/// How moves are written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotationStyle {
+    San,        // Nf3
+    Figurine,   // ♘f3
+    Long,       // Ng1-f3
+    Uci,        // g1f3
+    Localized(Language)     // Sf3
+}
 
-    // start the return value with the piece
-    let mut ret = if piece == Piece::Pawn {
-        "".to_string()
-    } else {
-        piece.to_string(Color::White)
-    };
+impl NotationStyle {
+    /// The letter (or figurine) for a piece, pawns don't have one
+    fn piece(&self, piece :Piece, color :Color) -> String {
+        let index = match piece {
+            Piece::King => 0,
+            Piece::Queen => 1,
+            Piece::Rook => 2,
+            Piece::Bishop => 3,
+            Piece::Knight => 4,
+            Piece::Pawn => return String::new()
+        };
+
+        match self {
+            NotationStyle::Figurine if color == Color::White => ["♔", "♕", "♖", "♗", "♘"][index].to_string(),
+            NotationStyle::Figurine => ["♚", "♛", "♜", "♝", "♞"][index].to_string(),
+            NotationStyle::Localized(language) => language.letters()[index].to_string(),
+            _ => piece.to_string(Color::White)
+        }
+    }
+}
+
+/// Converts a move into Standard Algebraic Notation (SAN) for the board it's played on
+pub fn to_notation(chess_move :&ChessMove, board :&Board) -> String {
+    to_styled_notation(chess_move, board, NotationStyle::San)
+}
 
-    // generate all the valid moves
-    let mut move_gen = MoveGen::new_legal(board);
+/// Converts a move into the given notation style for the board it's played on
+pub fn to_styled_notation(chess_move :&ChessMove, board :&Board, style :NotationStyle) -> String {
+    let source = chess_move.get_source();
+    let dest = chess_move.get_dest();
 
-    // only want moves that land on the same square
-    move_gen.set_iterator_mask(BitBoard::from_square(chess_move.get_dest()));
+    let piece = board.piece_on(source);
 
-    // filter out moves that aren't with the same piece
-    let mut file_rank_same_pieces = move_gen
-        .filter(|mv| board.piece_on(mv.get_source()).unwrap() == piece)
-        .map(|mv| (mv.get_source().get_file(), mv.get_source().get_rank()))
-        .collect::<Vec<_>>();
+    // this is probably an error, so just return the source->destination
+    if piece.is_none() || style == NotationStyle::Uci {
+        return format!("{}", chess_move);
+    }
 
-    // have to dedup because Rank & File don't impl Eq :-(
-    file_rank_same_pieces.sort_unstable_by(|(f1, r1), (f2, r2)| f1.to_index().cmp(&f2.to_index()).then(r1.to_index().cmp(&r2.to_index())));
-    file_rank_same_pieces.dedup();
+    let piece = piece.unwrap();
+    let color = board.side_to_move();
 
-    println!("SAME: {:?}", file_rank_same_pieces);
+    // pawns only change files when capturing, which covers en passant too
+    let is_capture = board.piece_on(dest).is_some() || (piece == Piece::Pawn && source.get_file() != dest.get_file());
 
-    // check to see if their are 2 pieces on the same files
-    if file_rank_same_pieces.len() > 1 {
-        if file_rank_same_pieces.iter().map(|(f, r)| f.to_index()).counts().values().any(|v| *v > 1) {
-            // check to see if there are 2 pieces on the same rank
-            ret += if file_rank_same_pieces.iter().map(|(f, r)| r.to_index()).counts().values().any(|v| *v > 1) {
-                // we need a full coordinate here
-                format!("{}", chess_move.get_source())
-            } else {
-                // rank is enough to disambiguate
-                format!("{}", chess_move.get_source().get_rank().to_index() + 1)
-            }.as_str()
+    let mut ret = if piece == Piece::King && (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs() == 2 {
+        // castling is the only time the king moves 2 squares
+        if dest.get_file() == File::G { "O-O".to_string() } else { "O-O-O".to_string() }
+    } else if style == NotationStyle::Long {
+        format!("{}{}{}{}", style.piece(piece, color), source, if is_capture { "x" } else { "-" }, dest)
+    } else if piece == Piece::Pawn {
+        if is_capture {
+            format!("{}x{}", file_letter(source.get_file()), dest)
         } else {
-            // the file is enough to disambiguate
-            ret += format!("{}", format!("{:?}", chess_move.get_source().get_file()).to_ascii_lowercase()).as_str()
+            dest.to_string()
         }
-    }
+    } else {
+        let mut ret = style.piece(piece, color);
 
-    // add an 'x' if we have a capture
-    if board.piece_on(chess_move.get_dest()).is_some() {
-        ret += "x";
-    }
+        // other pieces of the same kind that can legally move to the same square
+        let mut move_gen = MoveGen::new_legal(board);
+        move_gen.set_iterator_mask(BitBoard::from_square(dest));
 
-    // add on the destination
-    ret += dest.to_string().as_str();
+        let others = move_gen
+            .map(|mv| mv.get_source())
+            .filter(|sq| *sq != source && board.piece_on(*sq) == Some(piece))
+            .collect::<Vec<_>>();
+
+        // the file if it's enough, then the rank, otherwise the whole square
+        if !others.is_empty() {
+            if others.iter().all(|sq| sq.get_file() != source.get_file()) {
+                ret.push(file_letter(source.get_file()));
+            } else if others.iter().all(|sq| sq.get_rank() != source.get_rank()) {
+                ret += (source.get_rank().to_index() + 1).to_string().as_str();
+            } else {
+                ret += source.to_string().as_str();
+            }
+        }
+
+        // add an 'x' if we have a capture
+        if is_capture {
+            ret += "x";
+        }
+
+        ret + dest.to_string().as_str()
+    };
 
     // check to see if we have a promotion
     if let Some(p) = chess_move.get_promotion() {
-        ret += format!("={}", p.to_string(Color::White)).as_str();
+        ret += format!("={}", style.piece(p, color)).as_str();
     }
 
-    // check for mate
-    if board.status() == BoardStatus::Checkmate {
+    // check & mate are for the position after the move
+    let after = board.make_move_new(*chess_move);
+
+    if after.status() == BoardStatus::Checkmate {
         ret += "#";
-    } else if board.checkers().popcnt() != 0 {
-        ret += "+"; // see if there's a check
+    } else if after.checkers().popcnt() != 0 {
+        ret += "+";
     }
 
     ret
 }
 
+fn file_letter(file :File) -> char {
+    (b'a' + file.to_index() as u8) as char
+}
+
+/// Reads a move for the board from SAN (Nbd7, exd8=Q+, O-O-O), lenient forms (e8Q, 0-0),
+/// or long algebraic (e2e4, Ng1-f3, e7e8q)
+pub fn from_notation(notation :&str, board :&Board) -> Result<ChessMove, NotationError> {
+    // check marks & annotations don't change the move
+    let text = notation.trim().trim_end_matches(|c| "+#!?".contains(c));
+    let text = text.strip_suffix("e.p.").unwrap_or(text).trim();
+
+    let legal_moves = MoveGen::new_legal(board).collect::<Vec<_>>();
+
+    // castling, also written with zeros
+    let castle = text.replace('0', "O");
+
+    if castle == "O-O" || castle == "O-O-O" {
+        let dest_file = if castle == "O-O" { File::G } else { File::C };
+
+        let candidates = legal_moves.into_iter().filter(|mv| {
+            board.piece_on(mv.get_source()) == Some(Piece::King) &&
+                mv.get_source().get_file() == File::E &&
+                mv.get_dest().get_file() == dest_file
+        }).collect();
+
+        return select(notation, candidates);
+    }
+
+    let chars = text.chars().collect::<Vec<_>>();
+
+    // the piece letter, only uppercase so bxc3 is a pawn
+    let (letter_piece, rest) = match chars.first().and_then(|c| letter_to_piece(*c)) {
+        Some(piece) => (Some(piece), &chars[1..]),
+        None => (None, &chars[..])
+    };
+
+    // a promotion at the end: =Q, Q, or q
+    let (rest, promotion) = match rest.last().and_then(|c| letter_to_piece(c.to_ascii_uppercase())) {
+        Some(piece) if piece != Piece::King && piece != Piece::Pawn => {
+            let rest = &rest[..rest.len() - 1];
+            (rest.strip_suffix(&['=']).unwrap_or(rest), Some(piece))
+        },
+        _ => (rest, None)
+    };
+
+    // what's left is squares: an optional file and/or rank of the source, then the destination
+    let squares = rest.iter().filter(|c| !"x-:".contains(**c)).collect::<Vec<_>>();
+
+    if squares.len() < 2 || squares.len() > 4 {
+        return Err(NotationError::Invalid(notation.to_string()));
+    }
+
+    let dest = parse_square(*squares[squares.len() - 2], *squares[squares.len() - 1])
+        .ok_or_else(|| NotationError::Invalid(notation.to_string()))?;
+
+    let mut source_file = None;
+    let mut source_rank = None;
+
+    for c in squares[..squares.len() - 2].iter() {
+        match c {
+            'a'..='h' if source_file.is_none() => { source_file = Some(File::from_index(**c as usize - 'a' as usize)); },
+            '1'..='8' if source_rank.is_none() => { source_rank = Some(Rank::from_index(**c as usize - '1' as usize)); },
+            _ => return Err(NotationError::Invalid(notation.to_string()))
+        }
+    }
+
+    // without a letter it's a pawn, unless the whole source square is given (e2e4 & g1f3 are both fine)
+    let piece = match letter_piece {
+        Some(piece) => Some(piece),
+        None if source_file.is_some() && source_rank.is_some() => None,
+        None => Some(Piece::Pawn)
+    };
+
+    // leaving off the promotion piece matches all of them, so it's reported as ambiguous
+    let candidates = legal_moves.into_iter().filter(|mv| {
+        mv.get_dest() == dest &&
+            (promotion.is_none() || mv.get_promotion() == promotion) &&
+            piece.map_or(true, |piece| board.piece_on(mv.get_source()) == Some(piece)) &&
+            source_file.map_or(true, |file| mv.get_source().get_file() == file) &&
+            source_rank.map_or(true, |rank| mv.get_source().get_rank() == rank)
+    }).collect();
+
+    select(notation, candidates)
+}
+
+/// The one move that matches, or why there isn't one
+fn select(notation :&str, mut candidates :Vec<ChessMove>) -> Result<ChessMove, NotationError> {
+    match candidates.len() {
+        0 => Err(NotationError::Illegal(notation.to_string())),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(NotationError::Ambiguous(notation.to_string(), candidates))
+    }
+}
+
+fn letter_to_piece(letter :char) -> Option<Piece> {
+    match letter {
+        'K' => Some(Piece::King),
+        'Q' => Some(Piece::Queen),
+        'R' => Some(Piece::Rook),
+        'B' => Some(Piece::Bishop),
+        'N' => Some(Piece::Knight),
+        _ => None
+    }
+}
+
+fn parse_square(file :char, rank :char) -> Option<Square> {
+    match (file, rank) {
+        ('a'..='h', '1'..='8') => Some(Square::make_square(
+            Rank::from_index(rank as usize - '1' as usize),
+            File::from_index(file as usize - 'a' as usize)
+        )),
+        _ => None
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
-    use chess::{BoardBuilder, Board, Piece, Color, Rank, Square, ChessMove};
+    use chess::{BoardBuilder, Board, Piece, Color, Square, ChessMove};
     use std::convert::TryFrom;
-    use crate::chess_utils::to_notation;
+    use std::str::FromStr;
+    use crate::chess_utils::{to_notation, to_styled_notation, from_notation, NotationError, NotationStyle, Language};
 
     fn make_board() -> Board {
         Board::try_from(BoardBuilder::new()
@@ -108,15 +297,158 @@ mod tests {
         ).unwrap()
     }
 
+    fn san(fen :&str, mv :&str) -> String {
+        to_notation(&ChessMove::from_str(mv).unwrap(), &Board::from_str(fen).unwrap())
+    }
+
     #[test]
     fn standard_move() {
         let board = make_board();
 
-        assert_eq!("Qh1xe1".to_string(), to_notation(&ChessMove::new(Square::H1, Square::E1, None), &board));
+        // Qh4 shares the file, but nothing else on the 1st rank can get to e1
+        assert_eq!("Q1xe1".to_string(), to_notation(&ChessMove::new(Square::H1, Square::E1, None), &board));
         assert_eq!("Q1h2".to_string(), to_notation(&ChessMove::new(Square::H1, Square::H2, None), &board));
         assert_eq!("Qef4".to_string(), to_notation(&ChessMove::new(Square::E4, Square::F4, None), &board));
         assert_eq!("Qd3".to_string(), to_notation(&ChessMove::new(Square::E4, Square::D3, None), &board));
         assert_eq!("a8=Q".to_string(), to_notation(&ChessMove::new(Square::A7, Square::A8, Some(Piece::Queen)), &board));
-        // assert_eq!("Qh6+".to_string(), to_notation(&ChessMove::new(Square::H4, Square::H6, None), &board));
+        assert_eq!("Qh6+".to_string(), to_notation(&ChessMove::new(Square::H4, Square::H6, None), &board));
+    }
+
+    #[test]
+    fn castling() {
+        assert_eq!("O-O", san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"));
+        assert_eq!("O-O-O", san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1"));
+        assert_eq!("O-O", san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8g8"));
+        assert_eq!("O-O-O", san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8"));
+
+        // castling into check
+        assert_eq!("O-O+", san("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"));
+    }
+
+    #[test]
+    fn pawns() {
+        assert_eq!("e4", san("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2e4"));
+        assert_eq!("exd5", san("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", "e4d5"));
+
+        // en passant lands on an empty square
+        assert_eq!("exf6", san("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "e5f6"));
+
+        // capturing promotion with check
+        assert_eq!("exd8=Q+", san("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q"));
+        assert_eq!("e8=N", san("7k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8n"));
+    }
+
+    #[test]
+    fn check_and_mate() {
+        assert_eq!("Bb5+", san("rnbqkbnr/ppp2ppp/3p4/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3", "f1b5"));
+        assert_eq!("Qxf7#", san("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", "h5f7"));
+    }
+
+    #[test]
+    fn disambiguation() {
+        // file
+        assert_eq!("Nbd7", san("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "b8d7"));
+
+        // rank
+        assert_eq!("R1a3", san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"));
+
+        // both, with 3 queens
+        assert_eq!("Qh4e1", san("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1"));
+
+        // a pinned knight can't move, so there's nothing to disambiguate
+        assert_eq!("Ne2", san("rnbqk1nr/pppp1ppp/8/4p3/1b2P3/2N5/PPP2PPP/R1BQKBNR w KQkq - 0 1", "g1e2"));
     }
-}
\ No newline at end of file
+
+    fn styled(fen :&str, mv :&str, style :NotationStyle) -> String {
+        to_styled_notation(&ChessMove::from_str(mv).unwrap(), &Board::from_str(fen).unwrap(), style)
+    }
+
+    #[test]
+    fn styles() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!("♘f3", styled(start, "g1f3", NotationStyle::Figurine));
+        assert_eq!("♞f6", styled("rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1", "g8f6", NotationStyle::Figurine));
+        assert_eq!("Ng1-f3", styled(start, "g1f3", NotationStyle::Long));
+        assert_eq!("e2-e4", styled(start, "e2e4", NotationStyle::Long));
+        assert_eq!("g1f3", styled(start, "g1f3", NotationStyle::Uci));
+        assert_eq!("Sf3", styled(start, "g1f3", NotationStyle::Localized(Language::German)));
+        assert_eq!("Cf3", styled(start, "g1f3", NotationStyle::Localized(Language::French)));
+
+        // captures, promotions, castling, and checks in the other styles
+        assert_eq!("Qh5xf7#", styled("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", "h5f7", NotationStyle::Long));
+        assert_eq!("e5xf6", styled("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "e5f6", NotationStyle::Long));
+        assert_eq!("exd8=D+", styled("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q", NotationStyle::Localized(Language::German)));
+        assert_eq!("exd8=♕+", styled("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q", NotationStyle::Figurine));
+        assert_eq!("O-O-O", styled("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1", NotationStyle::Long));
+    }
+
+    fn parse(fen :&str, notation :&str) -> Result<String, NotationError> {
+        from_notation(notation, &Board::from_str(fen).unwrap()).map(|mv| mv.to_string())
+    }
+
+    #[test]
+    fn parse_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!(Ok("e2e4".to_string()), parse(start, "e4"));
+        assert_eq!(Ok("g1f3".to_string()), parse(start, "Nf3"));
+        assert_eq!(Ok("b8d7".to_string()), parse("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "Nbd7"));
+        assert_eq!(Ok("e7d8q".to_string()), parse("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "exd8=Q+"));
+        assert_eq!(Ok("e5f6".to_string()), parse("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "exf6 e.p."));
+        assert_eq!(Ok("h5f7".to_string()), parse("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", "Qxf7#"));
+        assert_eq!(Ok("h4e1".to_string()), parse("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "Qh4e1"));
+        assert_eq!(Ok("a1a3".to_string()), parse("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3"));
+    }
+
+    #[test]
+    fn parse_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+        assert_eq!(Ok("e1g1".to_string()), parse(fen, "O-O"));
+        assert_eq!(Ok("e1c1".to_string()), parse(fen, "O-O-O"));
+        assert_eq!(Ok("e1g1".to_string()), parse(fen, "0-0"));
+        assert_eq!(Ok("e8c8".to_string()), parse("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "0-0-0"));
+        assert_eq!(Err(NotationError::Illegal("O-O".to_string())), parse("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1", "O-O"));
+    }
+
+    #[test]
+    fn parse_lenient() {
+        let fen = "7k/4P3/8/8/8/8/8/4K3 w - - 0 1";
+
+        assert_eq!(Ok("e7e8q".to_string()), parse(fen, "e8Q"));
+        assert_eq!(Ok("e7e8n".to_string()), parse(fen, "e8=N"));
+        assert_eq!(Ok("e7e8r".to_string()), parse(fen, "e7e8r"));
+
+        // long algebraic
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!(Ok("e2e4".to_string()), parse(start, "e2e4"));
+        assert_eq!(Ok("e2e4".to_string()), parse(start, "e2-e4"));
+        assert_eq!(Ok("g1f3".to_string()), parse(start, "g1f3"));
+        assert_eq!(Ok("g1f3".to_string()), parse(start, "Ng1-f3"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        assert_eq!(Err(NotationError::Invalid("hello".to_string())), parse(start, "hello"));
+        assert_eq!(Err(NotationError::Invalid("".to_string())), parse(start, ""));
+        assert_eq!(Err(NotationError::Invalid("Nz9".to_string())), parse(start, "Nz9"));
+        assert_eq!(Err(NotationError::Illegal("e5".to_string())), parse(start, "e5"));
+        assert_eq!(Err(NotationError::Illegal("Bc4".to_string())), parse(start, "Bc4"));
+
+        // two knights can get to d7
+        match parse("rnbqkb1r/ppp1pppp/5n2/3p4/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", "Nd7") {
+            Err(NotationError::Ambiguous(_, moves)) => assert_eq!(2, moves.len()),
+            result => panic!("Expected ambiguous, got {:?}", result)
+        }
+
+        // a promotion needs a piece
+        match parse("7k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8") {
+            Err(NotationError::Ambiguous(_, moves)) => assert_eq!(4, moves.len()),
+            result => panic!("Expected ambiguous, got {:?}", result)
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::time::{Duration, Instant};
+
+use chess::Color;
+
+/// How time is given back for each move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bonus {
+    Fischer,    // the increment is added after every move
+    Bronstein,  // the time used is given back after every move, up to the increment
+    Delay       // the clock waits for the increment before it starts running down
+}
+
+/// The time each side starts with, and what they get back for each move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,    // the increment for Fischer, the delay for Bronstein & Delay
+    pub bonus: Bonus
+}
+
+impl TimeControl {
+    /// The increment an engine should be told about, it only knows about Fischer increments
+    pub fn uci_increment(&self) -> Duration {
+        match self.bonus {
+            Bonus::Fischer => self.increment,
+            Bonus::Bronstein | Bonus::Delay => Duration::from_secs(0)
+        }
+    }
+}
+
+/// A chess clock: each side's remaining time, and whose clock is running
+/// Times are computed from the Instant passed in, so the clock doesn't need to be ticked
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clock {
+    pub control: TimeControl,
+    remaining: [Duration; 2],           // white's & black's, as of when their clocks last stopped
+    running: Option<(Color, Instant)>,  // whose clock is running, and since when
+    bonuses: Vec<(Color, Duration)>     // the bonus each press added, so a takeback can remove it
+}
+
+impl Clock {
+    pub fn new(control :TimeControl) -> Self {
+        Clock {
+            control,
+            remaining: [control.base, control.base],
+            running: None,
+            bonuses: Vec::new()
+        }
+    }
+
+    /// Whose clock is running, if either
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _since)| color)
+    }
+
+    /// The time a side has left
+    pub fn remaining(&self, color :Color, now :Instant) -> Duration {
+        let remaining = self.remaining[color.to_index()];
+
+        match self.running {
+            Some((running, since)) if running == color => remaining.saturating_sub(self.charged(now.saturating_duration_since(since))),
+            _ => remaining
+        }
+    }
+
+    /// The side whose time ran out, if one has
+    pub fn flagged(&self, now :Instant) -> Option<Color> {
+        self.running().filter(|color| self.remaining(*color, now) == Duration::from_secs(0))
+    }
+
+    /// Starts a side's clock, stopping the other's without any bonus
+    pub fn start(&mut self, color :Color, now :Instant) {
+        self.stop(now);
+        self.running = Some((color, now));
+    }
+
+    /// Stops whichever clock is running
+    pub fn stop(&mut self, now :Instant) {
+        if let Some(color) = self.running() {
+            self.remaining[color.to_index()] = self.remaining(color, now);
+            self.running = None;
+        }
+    }
+
+    /// The side to move finished its move: stop its clock, add its bonus, and start the other side's
+    pub fn press(&mut self, now :Instant) {
+        let (color, since) = match self.running {
+            Some(running) => running,
+            None => return
+        };
+
+        let used = now.saturating_duration_since(since);
+        let remaining = self.remaining(color, now);
+
+        // a side whose flag fell doesn't get time back
+        let bonus = if remaining > Duration::from_secs(0) {
+            match self.control.bonus {
+                Bonus::Fischer => self.control.increment,
+                Bonus::Bronstein => used.min(self.control.increment),
+                Bonus::Delay => Duration::from_secs(0)
+            }
+        } else {
+            Duration::from_secs(0)
+        };
+
+        self.remaining[color.to_index()] = remaining + bonus;
+        self.bonuses.push((color, bonus));
+        self.running = Some((!color, now));
+    }
+
+    /// Takes back the last few presses, removing the bonus each one added, then starts a side's clock
+    /// The time spent thinking about the moves stays used
+    pub fn take_back(&mut self, presses :usize, color :Color, now :Instant) {
+        self.stop(now);
+
+        for _ in 0..presses {
+            if let Some((pressed, bonus)) = self.bonuses.pop() {
+                self.remaining[pressed.to_index()] = self.remaining[pressed.to_index()].saturating_sub(bonus);
+            }
+        }
+
+        self.start(color, now);
+    }
+
+    /// The time taken off the clock for thinking this long, the delay is free
+    fn charged(&self, used :Duration) -> Duration {
+        match self.control.bonus {
+            Bonus::Delay => used.saturating_sub(self.control.increment),
+            Bonus::Fischer | Bonus::Bronstein => used
+        }
+    }
+}
+
+/// The time as minutes & seconds, with tenths once it's under 10 seconds
+pub fn format_time(time :Duration) -> String {
+    if time < Duration::from_secs(10) {
+        format!("0:{:04.1}", time.as_secs_f64())
+    } else {
+        format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chess::Color;
+
+    use crate::clock::{format_time, Bonus, Clock, TimeControl};
+
+    fn make_clock(bonus :Bonus) -> Clock {
+        Clock::new(TimeControl { base: Duration::from_secs(60), increment: Duration::from_secs(5), bonus })
+    }
+
+    fn secs(secs :u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn fischer() {
+        let start = Instant::now();
+        let mut clock = make_clock(Bonus::Fischer);
+
+        clock.start(Color::White, start);
+        assert_eq!(secs(50), clock.remaining(Color::White, start + secs(10)));
+        assert_eq!(secs(60), clock.remaining(Color::Black, start + secs(10)));
+
+        // the increment comes after the move, however long it took
+        clock.press(start + secs(2));
+        assert_eq!(Some(Color::Black), clock.running());
+        assert_eq!(secs(63), clock.remaining(Color::White, start + secs(10)));
+        assert_eq!(secs(52), clock.remaining(Color::Black, start + secs(10)));
+    }
+
+    #[test]
+    fn bronstein() {
+        let start = Instant::now();
+        let mut clock = make_clock(Bonus::Bronstein);
+
+        // quick moves get back exactly what they used, slow ones only the increment
+        clock.start(Color::White, start);
+        clock.press(start + secs(2));
+        assert_eq!(secs(60), clock.remaining(Color::White, start + secs(2)));
+
+        clock.press(start + secs(22));
+        assert_eq!(secs(45), clock.remaining(Color::Black, start + secs(22)));
+    }
+
+    #[test]
+    fn delay() {
+        let start = Instant::now();
+        let mut clock = make_clock(Bonus::Delay);
+
+        // nothing comes off until the delay is over
+        clock.start(Color::White, start);
+        assert_eq!(secs(60), clock.remaining(Color::White, start + secs(4)));
+        assert_eq!(secs(55), clock.remaining(Color::White, start + secs(10)));
+
+        clock.press(start + secs(10));
+        assert_eq!(secs(55), clock.remaining(Color::White, start + secs(20)));
+    }
+
+    #[test]
+    fn flag_fall() {
+        let start = Instant::now();
+        let mut clock = make_clock(Bonus::Fischer);
+
+        clock.start(Color::White, start);
+        assert_eq!(None, clock.flagged(start + secs(59)));
+        assert_eq!(Some(Color::White), clock.flagged(start + secs(61)));
+
+        // too late for the increment
+        clock.press(start + secs(61));
+        assert_eq!(secs(0), clock.remaining(Color::White, start + secs(70)));
+
+        clock.stop(start + secs(70));
+        assert_eq!(None, clock.running());
+        assert_eq!(secs(51), clock.remaining(Color::Black, start + secs(80)));
+    }
+
+    #[test]
+    fn take_back() {
+        let start = Instant::now();
+        let mut clock = make_clock(Bonus::Fischer);
+
+        clock.start(Color::White, start);
+        clock.press(start + secs(2));
+        clock.press(start + secs(5));
+
+        // both increments come off, the thinking time doesn't come back
+        clock.take_back(2, Color::White, start + secs(5));
+        assert_eq!(Some(Color::White), clock.running());
+        assert_eq!(secs(58), clock.remaining(Color::White, start + secs(5)));
+        assert_eq!(secs(57), clock.remaining(Color::Black, start + secs(5)));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!("5:00", format_time(secs(300)));
+        assert_eq!("1:05", format_time(secs(65)));
+        assert_eq!("0:09.5", format_time(Duration::from_millis(9_500)));
+    }
+}
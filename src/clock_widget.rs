@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use druid::{Widget, EventCtx, LifeCycle, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, Point, Color, TextLayout, TimerToken};
+use druid::RenderContext;
+
+use crate::State;
+use crate::clock::format_time;
+use crate::game_over;
+
+const TICK :Duration = Duration::from_millis(100);
+const HEIGHT :f64 = 40.0;
+const RUNNING :Color = Color::rgb8(0x30, 0x50, 0x80);
+const LOW_TIME :Duration = Duration::from_secs(10);    // shown in red from here on
+
+/// One side's time, highlighted while it's running
+/// Each clock watches for its own side's flag falling
+pub struct ClockWidget {
+    color: chess::Color,
+    timer: TimerToken
+}
+
+impl ClockWidget {
+    pub fn new(color :chess::Color) -> Self {
+        ClockWidget {
+            color,
+            timer: TimerToken::INVALID
+        }
+    }
+}
+
+impl Widget<State> for ClockWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut State, _env: &Env) {
+        if let Event::Timer(token) = event {
+            if *token != self.timer {
+                return;
+            }
+
+            let flagged = data.clock.as_ref().and_then(|clock| clock.flagged(Instant::now()));
+
+            if flagged == Some(self.color) && data.result().is_none() {
+                data.flag_fall(self.color);
+                game_over::show(ctx, data);
+            }
+
+            ctx.request_paint();
+            self.timer = ctx.request_timer(TICK);
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, _data: &State, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.timer = ctx.request_timer(TICK);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &State, data: &State, _env: &Env) {
+        if old_data.clock != data.clock {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &State, _env: &Env) -> Size {
+        bc.constrain(Size::new(bc.max().width, HEIGHT))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &State, env: &Env) {
+        let size = ctx.size();
+        let clock = data.clock.as_ref();
+
+        let background = if clock.and_then(|clock| clock.running()) == Some(self.color) { RUNNING } else { Color::BLACK };
+        ctx.fill(size.to_rect(), &background);
+
+        // untimed games don't have a clock
+        let (time, text_color) = match clock.map(|clock| clock.remaining(self.color, Instant::now())) {
+            Some(remaining) if remaining < LOW_TIME => (format_time(remaining), Color::RED),
+            Some(remaining) => (format_time(remaining), Color::WHITE),
+            None => ("-:--".to_string(), Color::grey(0.6))
+        };
+
+        let mut label = TextLayout::<String>::from_text(format!("{:?}  {}", self.color, time));
+        label.set_text_color(text_color);
+        label.set_text_size(20.0);
+
+        label.rebuild_if_needed(ctx.text(), env);
+        label.draw(ctx, Point::new(7.0, (HEIGHT - label.size().height) / 2.0));
+    }
+}
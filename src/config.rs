@@ -0,0 +1,20 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use log::error;
+
+/// The directory where we keep files between sessions: ~/.cgir
+/// It is created if it doesn't exist yet
+pub fn data_dir() -> PathBuf {
+    let dir = env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join(".cgir");
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        error!("Error creating {}: {:?}", dir.display(), e);
+    }
+
+    dir
+}
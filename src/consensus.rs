@@ -0,0 +1,180 @@
+use chess::{Board, ChessMove, Game};
+use itertools::Itertools;
+
+use crate::chess_utils::{to_styled_notation, NotationStyle};
+use crate::uci::{Uci, Analysis, PossibleMove, MATE_SCORE};
+
+pub const CONSENSUS_DEPTH :u8 = 12;
+
+const EVAL_THRESHOLD :i32 = 50;    // centipawns the evaluations can differ by before we flag it
+
+/// What one engine found for the position
+#[derive(Clone, Debug)]
+pub struct EngineLines {
+    pub engine: String,
+    pub lines: Vec<PossibleMove>,   // the final line for each MultiPV, best first
+    pub best_move: Option<ChessMove>
+}
+
+impl EngineLines {
+    /// The score of the engine's top line, from the side to move's point of view
+    pub fn score(&self) -> Option<i32> {
+        self.lines.first().map(|pm| pm.score)
+    }
+
+    /// The engine's name, followed by each of its lines
+    pub fn describe(&self, board :&Board, style :NotationStyle) -> String {
+        let lines = self.lines.iter().map(|pm| {
+            format!("{:>6} {}", format_score(pm.score), format_line(&pm.moves, board, style))
+        });
+
+        std::iter::once(self.engine.clone()).chain(lines).join("\n")
+    }
+}
+
+/// Every engine's lines for a position
+#[derive(Clone, Debug)]
+pub struct Consensus {
+    pub board: Board,
+    pub results: Vec<EngineLines>
+}
+
+impl Consensus {
+    /// Each engine's lines, to be shown side by side
+    pub fn columns(&self, style :NotationStyle) -> Vec<String> {
+        self.results.iter().map(|result| result.describe(&self.board, style)).collect()
+    }
+
+    pub fn disagreements(&self, style :NotationStyle) -> Vec<String> {
+        disagreements(&self.results, &self.board, style)
+    }
+}
+
+/// Searches the position with every engine at once, and waits for all of them to finish
+/// Another search started on one of the engines stops its part early, so its lines may not reach the depth
+pub fn compare(engines :Vec<Uci>, game :&Game, depth :u8) -> Consensus {
+    // start all the searches before reading any, so the engines run in parallel
+    let searches = engines.into_iter().map(|mut uci| {
+        let rx = uci.analyze(game, vec![], Some(depth));
+        (uci.name().to_string(), rx)
+    }).collect_vec();
+
+    let results = searches.into_iter().map(|(engine, rx)| {
+        let mut lines = Vec::<PossibleMove>::new();
+        let mut best_move = None;
+
+        for analysis in rx.iter() {
+            match analysis {
+                Analysis::PossibleMove(pm) if !pm.moves.is_empty() => {
+                    // keep only the latest line for each MultiPV
+                    lines.retain(|line| line.multi_pv != pm.multi_pv);
+                    lines.push(pm);
+                },
+                Analysis::BestMove(mv) => { best_move = Some(mv); },
+                _ => ()
+            }
+        }
+
+        lines.sort_by_key(|pm| pm.multi_pv);
+
+        EngineLines { engine, lines, best_move }
+    }).collect();
+
+    Consensus { board: game.current_position(), results }
+}
+
+/// Where the engines don't agree, on the best move or by more than EVAL_THRESHOLD on the evaluation
+pub fn disagreements(results :&[EngineLines], board :&Board, style :NotationStyle) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    let best_moves = results.iter().filter_map(|result| result.best_move).unique().collect_vec();
+
+    if best_moves.len() > 1 {
+        notes.push(format!("Best move: {}", results.iter()
+            .filter_map(|result| result.best_move.map(|mv| format!("{} {}", result.engine, to_styled_notation(&mv, board, style))))
+            .join(" vs ")));
+    }
+
+    let scores = results.iter().filter_map(|result| result.score()).collect_vec();
+
+    if let (Some(min), Some(max)) = (scores.iter().min(), scores.iter().max()) {
+        if max - min > EVAL_THRESHOLD {
+            notes.push(format!("Evaluation: {}", results.iter()
+                .filter_map(|result| result.score().map(|score| format!("{} {}", result.engine, format_score(score))))
+                .join(" vs ")));
+        }
+    }
+
+    notes
+}
+
+/// Scores as pawns, or moves to mate
+pub fn format_score(score :i32) -> String {
+    if score.abs() > MATE_SCORE / 2 {
+        let mate = MATE_SCORE - score.abs();
+
+        if score > 0 { format!("#{}", mate) } else { format!("-#{}", mate) }
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+/// A principal variation written out move by move
+fn format_line(moves :&[ChessMove], board :&Board, style :NotationStyle) -> String {
+    let mut board = *board;
+
+    moves.iter().map(|mv| {
+        let notation = to_styled_notation(mv, &board, style);
+        board = board.make_move_new(*mv);
+        notation
+    }).join(" ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chess::{Board, ChessMove, Square};
+
+    use crate::chess_utils::NotationStyle;
+    use crate::consensus::{disagreements, format_score, EngineLines};
+    use crate::uci::{PossibleMove, MATE_SCORE};
+
+    fn make_lines(engine :&str, best_move :ChessMove, score :i32) -> EngineLines {
+        EngineLines {
+            engine: engine.to_string(),
+            lines: vec![PossibleMove { depth: 12, score, multi_pv: 1, moves: vec![best_move], ..PossibleMove::default() }],
+            best_move: Some(best_move)
+        }
+    }
+
+    #[test]
+    fn agreement() {
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let results = vec![make_lines("Stockfish", e4, 30), make_lines("Ethereal", e4, 45)];
+
+        assert!(disagreements(&results, &Board::default(), NotationStyle::San).is_empty());
+    }
+
+    #[test]
+    fn disagreement() {
+        let e4 = ChessMove::new(Square::E2, Square::E4, None);
+        let d4 = ChessMove::new(Square::D2, Square::D4, None);
+
+        let results = vec![make_lines("Stockfish", e4, 30), make_lines("Ethereal", d4, 30)];
+        let notes = disagreements(&results, &Board::default(), NotationStyle::San);
+        assert_eq!(1, notes.len());
+        assert!(notes[0].starts_with("Best move"));
+
+        let results = vec![make_lines("Stockfish", e4, 30), make_lines("Ethereal", e4, 120)];
+        let notes = disagreements(&results, &Board::default(), NotationStyle::San);
+        assert_eq!(vec!["Evaluation: Stockfish +0.30 vs Ethereal +1.20".to_string()], notes);
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!("+0.35", format_score(35));
+        assert_eq!("-1.20", format_score(-120));
+        assert_eq!("#3", format_score(MATE_SCORE - 3));
+        assert_eq!("-#2", format_score(-MATE_SCORE + 2));
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use crate::uci::Uci;
+
+pub const DEFAULT_LEVEL :usize = 3;
+
+/// A rung on the difficulty ladder, and the engine settings it maps to
+/// The skill levels, depths, and times are the ones lichess used for stockfish
+/// see: https://lichess.org/blog/U4mtoEQAAEEAgZRL/strongest-chess-player-ever
+/// lichess didn't publish ratings for them, so the Elo for each level is our own estimate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    pub level: usize,
+    pub skill_level: u8,    // stockfish's "Skill Level", 0 - 20
+    pub elo: i64,           // estimated, for rating the player, and for engines without a skill level
+    pub depth: u8,
+    pub move_time: Duration
+}
+
+pub const LEVELS :[Difficulty; 8] = [
+    Difficulty { level: 1, skill_level: 3, elo: 800, depth: 1, move_time: Duration::from_millis(50) },
+    Difficulty { level: 2, skill_level: 6, elo: 1100, depth: 2, move_time: Duration::from_millis(100) },
+    Difficulty { level: 3, skill_level: 9, elo: 1400, depth: 3, move_time: Duration::from_millis(150) },
+    Difficulty { level: 4, skill_level: 11, elo: 1700, depth: 4, move_time: Duration::from_millis(200) },
+    Difficulty { level: 5, skill_level: 14, elo: 2000, depth: 6, move_time: Duration::from_millis(250) },
+    Difficulty { level: 6, skill_level: 17, elo: 2300, depth: 8, move_time: Duration::from_millis(300) },
+    Difficulty { level: 7, skill_level: 20, elo: 2700, depth: 10, move_time: Duration::from_millis(350) },
+    Difficulty { level: 8, skill_level: 20, elo: 3000, depth: 12, move_time: Duration::from_millis(400) },
+];
+
+impl Difficulty {
+    /// The settings for a level, anything off the ladder is clamped to it
+    pub fn for_level(level :usize) -> Difficulty {
+        LEVELS[level.clamp(1, LEVELS.len()) - 1]
+    }
+
+    /// The level whose rating is closest to the player's
+    pub fn for_rating(rating :f64) -> Difficulty {
+        LEVELS.iter()
+            .min_by(|a, b| (a.elo as f64 - rating).abs().partial_cmp(&(b.elo as f64 - rating).abs()).unwrap())
+            .copied()
+            .unwrap()
+    }
+
+    /// Sets the engine's strength options for this level
+    pub fn apply(&self, uci :&mut Uci) {
+        // stockfish's UCI_LimitStrength overrides its skill level, so it's only used without one
+        if uci.has_option("Skill Level") {
+            uci.set_option("Skill Level", &self.skill_level.to_string());
+        } else if uci.has_option("UCI_LimitStrength") {
+            let limit_elo = uci.option_range("UCI_Elo").map_or(false, |(min, max)| min <= self.elo && self.elo <= max);
+
+            uci.set_option("UCI_LimitStrength", if limit_elo { "true" } else { "false" });
+
+            if limit_elo {
+                uci.set_option("UCI_Elo", &self.elo.to_string());
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::difficulty::{Difficulty, LEVELS};
+
+    #[test]
+    fn for_level() {
+        assert_eq!(9, Difficulty::for_level(3).skill_level);
+        assert_eq!(1, Difficulty::for_level(0).level);
+        assert_eq!(LEVELS.len(), Difficulty::for_level(100).level);
+    }
+
+    #[test]
+    fn for_rating() {
+        assert_eq!(1, Difficulty::for_rating(100.0).level);
+        assert_eq!(3, Difficulty::for_rating(1500.0).level);
+        assert_eq!(4, Difficulty::for_rating(1600.0).level);
+        assert_eq!(LEVELS.len(), Difficulty::for_rating(3500.0).level);
+    }
+}
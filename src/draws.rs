@@ -0,0 +1,227 @@
+use std::fmt;
+
+use chess::{BitBoard, Board, BoardStatus, Color, Game, Piece};
+
+use crate::game_tree::{GameTree, NodeId};
+use crate::uci::{Uci, Analysis};
+
+const OFFER_DEPTH :u8 = 10;     // how deep the engine looks before answering a draw offer
+const ACCEPT_SCORE :i32 = 25;   // centipawns, the engine takes a draw unless it's ahead by more than this
+
+/// Why a game is drawn, or could be
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawReason {
+    Stalemate,
+    InsufficientMaterial,   // neither side can ever checkmate
+    Repetition,             // the same position three times, with the same side to move
+    FiftyMoves              // 50 moves each without a capture or pawn move
+}
+
+impl DrawReason {
+    /// Stalemate & insufficient material end the game, the others have to be claimed
+    pub fn automatic(&self) -> bool {
+        matches!(self, DrawReason::Stalemate | DrawReason::InsufficientMaterial)
+    }
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f :&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawReason::Stalemate => write!(f, "stalemate"),
+            DrawReason::InsufficientMaterial => write!(f, "insufficient material"),
+            DrawReason::Repetition => write!(f, "threefold repetition"),
+            DrawReason::FiftyMoves => write!(f, "the fifty-move rule")
+        }
+    }
+}
+
+/// Looks for a draw in the position after the move at `id`
+pub fn detect(tree :&GameTree, id :NodeId) -> Option<DrawReason> {
+    let board = tree.node(id).board;
+
+    if board.status() == BoardStatus::Stalemate {
+        Some(DrawReason::Stalemate)
+    } else if insufficient_material(&board) {
+        Some(DrawReason::InsufficientMaterial)
+    } else if fifty_moves(tree, id) {
+        Some(DrawReason::FiftyMoves)
+    } else if tree.to_game_at(id).can_declare_draw() {
+        // the fifty-move rule is the only other draw the crate knows about
+        Some(DrawReason::Repetition)
+    } else {
+        None
+    }
+}
+
+/// No sequence of moves can end in checkmate: kings with at most one minor piece between them,
+/// or only bishops that are all on the same color squares
+pub fn insufficient_material(board :&Board) -> bool {
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    if heavy.popcnt() > 0 {
+        return false;
+    }
+
+    let knights = board.pieces(Piece::Knight);
+    let bishops = board.pieces(Piece::Bishop);
+
+    if (knights | bishops).popcnt() <= 1 {
+        return true;
+    }
+
+    // bishops that can't cover both colors can't help each other mate
+    knights.popcnt() == 0 && !both_colors(*bishops)
+}
+
+/// Could this side ever checkmate, for deciding if running out of time loses or draws (FIDE 6.9)
+/// Any legal series of moves counts, helpmates included, following lichess's insufficient mating material:
+/// a lone knight mates against anything but queens, and bishops on one color need an enemy knight or pawn to block with
+pub fn can_checkmate(board :&Board, color :Color) -> bool {
+    let ours = *board.color_combined(color);
+    let theirs = *board.color_combined(!color);
+    let heavy = board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let knights = board.pieces(Piece::Knight) & ours;
+    let bishops = board.pieces(Piece::Bishop) & ours;
+
+    if (heavy & ours).popcnt() > 0 {
+        true
+    } else if knights.popcnt() == 0 && bishops.popcnt() == 0 {
+        false
+    } else if knights.popcnt() == 1 && bishops.popcnt() == 0 {
+        (theirs & !(board.pieces(Piece::King) | board.pieces(Piece::Queen))).popcnt() > 0
+    } else if knights.popcnt() == 0 {
+        both_colors(*board.pieces(Piece::Bishop)) || (theirs & (board.pieces(Piece::Knight) | board.pieces(Piece::Pawn))).popcnt() > 0
+    } else {
+        true
+    }
+}
+
+/// Are there pieces on both light & dark squares
+fn both_colors(pieces :BitBoard) -> bool {
+    let light_squares = BitBoard::new(0x55aa_55aa_55aa_55aa);
+
+    (pieces & light_squares).popcnt() > 0 && (pieces & !light_squares).popcnt() > 0
+}
+
+/// 100 plies in a row without a capture or pawn move, up to the position after the move at `id`
+/// A FEN start's halfmove clock isn't kept by the board, so only moves from the game are counted
+fn fifty_moves(tree :&GameTree, id :NodeId) -> bool {
+    halfmove_clock(tree, id) >= 100
+}
+
+/// Plies since the last capture or pawn move in the line to the position after the move at `id`
+pub fn halfmove_clock(tree :&GameTree, id :NodeId) -> usize {
+    tree.path(id).into_iter().rev().take_while(|id| {
+        let node = tree.node(*id);
+        let board = tree.node(node.parent.unwrap()).board;
+        let chess_move = node.chess_move.unwrap();
+
+        board.piece_on(chess_move.get_source()) != Some(Piece::Pawn) && board.piece_on(chess_move.get_dest()).is_none()
+    }).count()
+}
+
+/// Does the engine playing `color` accept a draw in the game's position
+/// It searches the position, and accepts unless it thinks it's winning
+pub fn accepts_draw(uci :&mut Uci, game :&Game, color :Color) -> bool {
+    let score = uci.analyze(game, vec![], Some(OFFER_DEPTH)).iter().filter_map(|analysis| match analysis {
+        Analysis::PossibleMove(pm) if pm.multi_pv == 1 && !pm.moves.is_empty() => Some(pm.score),
+        _ => None
+    }).last();
+
+    // scores are from the side to move's point of view
+    match score {
+        Some(score) if game.current_position().side_to_move() == color => accepts_score(score),
+        Some(score) => accepts_score(-score),
+        None => false
+    }
+}
+
+/// The engine's score, from its point of view, is low enough to take a draw
+fn accepts_score(score :i32) -> bool {
+    score <= ACCEPT_SCORE
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove};
+
+    use crate::draws::{accepts_score, can_checkmate, detect, fifty_moves, insufficient_material, DrawReason};
+    use crate::game_tree::GameTree;
+    use crate::uci::MATE_SCORE;
+
+    fn play(tree :&mut GameTree, moves :&[&str]) {
+        for mv in moves.iter() {
+            tree.make_move(ChessMove::from_str(mv).unwrap());
+        }
+    }
+
+    #[test]
+    fn material() {
+        let board = |fen :&str| Board::from_str(fen).unwrap();
+
+        assert!(insufficient_material(&board("4k3/8/8/8/8/8/8/4K3 w - - 0 1")));
+        assert!(insufficient_material(&board("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1")));
+        assert!(insufficient_material(&board("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1")));   // both on dark squares
+
+        assert!(!insufficient_material(&board("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1")));  // opposite colors
+        assert!(!insufficient_material(&board("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1")));
+        assert!(!insufficient_material(&board("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")));
+
+        // a lone knight or bishop can helpmate, with an enemy pawn blocking the king's escape
+        assert!(can_checkmate(&board("4k3/4p3/8/8/8/8/8/2N1K3 w - - 0 1"), chess::Color::White));
+        assert!(can_checkmate(&board("4k3/4p3/8/8/8/8/8/2B1K3 w - - 0 1"), chess::Color::White));
+        assert!(can_checkmate(&board("4k3/4p3/8/8/8/8/8/2N1K3 w - - 0 1"), chess::Color::Black));
+        assert!(can_checkmate(&board("4k3/8/8/8/8/8/8/1BN1K3 w - - 0 1"), chess::Color::White));
+        assert!(can_checkmate(&board("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1"), chess::Color::White));
+
+        // but not with nothing to block with
+        assert!(!can_checkmate(&board("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), chess::Color::White));
+        assert!(!can_checkmate(&board("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1"), chess::Color::White));
+        assert!(!can_checkmate(&board("3qk3/8/8/8/8/8/8/2N1K3 w - - 0 1"), chess::Color::White));
+        assert!(!can_checkmate(&board("3rk3/8/8/8/8/8/8/2B1K3 w - - 0 1"), chess::Color::White));
+
+        // bishops on one color, unless the other side's bishop is on the other color
+        assert!(!can_checkmate(&board("4k3/8/8/8/8/B7/8/2B1K3 w - - 0 1"), chess::Color::White));
+        assert!(can_checkmate(&board("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), chess::Color::White));
+        assert!(!can_checkmate(&board("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"), chess::Color::White));
+        assert!(can_checkmate(&board("4k1b1/8/8/8/8/8/8/2B1K3 w - - 0 1"), chess::Color::White));
+    }
+
+    #[test]
+    fn repetition_and_fifty_moves() {
+        let mut tree = GameTree::default();
+
+        play(&mut tree, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert_eq!(None, detect(&tree, tree.current()));
+
+        play(&mut tree, &["f6g8"]);
+        assert_eq!(Some(DrawReason::Repetition), detect(&tree, tree.current()));
+
+        // the knights' shuffle only counts after the last pawn move
+        let mut tree = GameTree::default();
+        play(&mut tree, &["e2e4"]);
+
+        for _ in 0..24 {
+            play(&mut tree, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        }
+
+        play(&mut tree, &["g1f3", "g8f6", "f3g1"]);
+        assert!(!fifty_moves(&tree, tree.current()));
+
+        play(&mut tree, &["f6g8"]);
+        assert!(fifty_moves(&tree, tree.current()));
+        assert_eq!(Some(DrawReason::FiftyMoves), detect(&tree, tree.current()));
+    }
+
+    #[test]
+    fn accepting() {
+        assert!(accepts_score(0));
+        assert!(accepts_score(25));
+        assert!(accepts_score(-400));
+        assert!(!accepts_score(120));
+        assert!(!accepts_score(MATE_SCORE - 3));
+    }
+}
@@ -0,0 +1,248 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use log::{debug, error};
+
+use crate::bench;
+use crate::config;
+use crate::uci::Uci;
+
+const STOCKFISH :&str = "/usr/games/stockfish";
+const ETHEREAL :&str = "/usr/games/ethereal-chess";    // a second opinion for consensus analysis, if installed
+
+const MEMORY_FRACTION :usize = 4;   // use at most 1/4 of the available memory for hash
+const MIN_HASH :usize = 16;         // in MB, stockfish's default
+const MAX_HASH :usize = 2048;       // in MB, for all the engines together
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Opponent,   // the engine the human is playing against
+    Analysis    // blunder checks, hints, threats, and tablebase probes
+}
+
+/// Owns every running engine, and splits the machine's cores & memory between them
+/// Uci is a handle to the engine's process, so the engines are handed out as clones
+#[derive(Clone, Debug)]
+pub struct EngineManager {
+    cores: usize,
+    hash_budget: usize,     // MB of hash for all the engines together
+    engines: Vec<(Role, Uci)>,
+    opponent_thinking: Arc<AtomicBool>,  // shared with the threads waiting on the opponent's move
+    opponent_request: Arc<AtomicUsize>   // counts the moves asked of the opponent, so stale replies can be spotted
+}
+
+impl EngineManager {
+    /// Starts stockfish as both the opponent and the analysis engine, and ethereal for analysis if we have it
+    pub fn new() -> Self {
+        let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+
+        let memory = fs::read_to_string("/proc/meminfo").ok().and_then(|meminfo| available_memory(&meminfo));
+        let hash_budget = memory.map_or(MIN_HASH * 2, |memory| (memory / MEMORY_FRACTION).clamp(MIN_HASH, MAX_HASH));
+
+        debug!("{} cores, {:?} MB available, {} MB for hash", cores, memory, hash_budget);
+
+        let mut manager = EngineManager {
+            cores,
+            hash_budget,
+            engines: Vec::new(),
+            opponent_thinking: Arc::new(AtomicBool::new(false)),
+            opponent_request: Arc::new(AtomicUsize::new(0))
+        };
+
+        manager.start(Role::Opponent, STOCKFISH);
+        manager.start(Role::Analysis, STOCKFISH);
+
+        if Path::new(ETHEREAL).exists() {
+            manager.start(Role::Analysis, ETHEREAL);
+        }
+
+        manager
+    }
+
+    /// Starts an engine for the role, then re-divides the cores & hash across all the engines
+    pub fn start(&mut self, role :Role, engine_path :&str) {
+        let mut uci = Uci::start_engine(&mut Command::new(engine_path));
+
+        if role == Role::Analysis {
+            uci.set_option("UCI_AnalyseMode", "true");
+            uci.set_option("MultiPV", "5");
+
+            // keep the analysis around for the next session
+            if let Err(e) = uci.open_store(&config::data_dir().join("analysis.db")) {
+                error!("Error opening analysis store: {:?}", e);
+            }
+        }
+
+        self.engines.push((role, uci));
+        self.balance();
+    }
+
+    /// The engine the human is playing against
+    pub fn opponent(&self) -> Uci {
+        self.engine(Role::Opponent)
+    }
+
+    /// The first analysis engine
+    pub fn analysis(&self) -> Uci {
+        self.engine(Role::Analysis)
+    }
+
+    /// Every analysis engine, for comparing their lines
+    pub fn analysis_engines(&self) -> Vec<Uci> {
+        self.engines.iter()
+            .filter(|(role, _uci)| *role == Role::Analysis)
+            .map(|(_role, uci)| uci.clone())
+            .collect()
+    }
+
+    fn engine(&self, role :Role) -> Uci {
+        self.engines.iter()
+            .find(|(engine_role, _uci)| *engine_role == role)
+            .map(|(_role, uci)| uci.clone())
+            .expect("No engine for role")
+    }
+
+    /// Lets every engine probe the endgame tablebases
+    pub fn set_syzygy_path(&mut self, path :&Path) {
+        for (_role, uci) in self.engines.iter_mut() {
+            if uci.has_option("SyzygyPath") {
+                uci.set_option("SyzygyPath", &path.to_string_lossy());
+            }
+        }
+    }
+
+    /// Marks the opponent as searching for its move, which pauses background analysis
+    pub fn set_opponent_thinking(&self, thinking :bool) {
+        self.opponent_thinking.store(thinking, Ordering::SeqCst);
+    }
+
+    /// Starts a new request for the opponent's move, and marks it as thinking
+    /// Analysis still searching is stopped, so the opponent gets the whole machine
+    /// Returns the request's id, which only stays current until the next request or cancel
+    pub fn request_opponent_move(&self) -> usize {
+        self.set_opponent_thinking(true);
+
+        for mut uci in self.analysis_engines() {
+            uci.stop();
+        }
+
+        self.opponent_request.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Is this the opponent's latest request, or has it been replaced or cancelled
+    pub fn is_current_request(&self, request :usize) -> bool {
+        self.opponent_request.load(Ordering::SeqCst) == request
+    }
+
+    /// Stops the opponent's search, its reply goes stale so it's ignored
+    pub fn cancel_opponent_move(&self) {
+        if self.analysis_paused() {
+            self.opponent().stop();
+        }
+
+        self.opponent_request.fetch_add(1, Ordering::SeqCst);
+        self.set_opponent_thinking(false);
+    }
+
+    /// Background analysis waits while the opponent is thinking, so it gets the whole machine
+    pub fn analysis_paused(&self) -> bool {
+        self.opponent_thinking.load(Ordering::SeqCst)
+    }
+
+    /// Divides the cores & hash evenly, never going above what the benchmark found fastest
+    fn balance(&mut self) {
+        let threads = split(self.cores, self.engines.len());
+        let hashes = split(self.hash_budget, self.engines.len());
+        let recommended = bench::recommended_settings();
+
+        for (((role, uci), threads), hash) in self.engines.iter_mut().zip(threads).zip(hashes) {
+            let (threads, hash) = match recommended {
+                Some((max_threads, max_hash)) => (threads.min(max_threads), hash.min(max_hash)),
+                None => (threads, hash)
+            };
+
+            debug!("{:?} {}: Threads {} Hash {}", role, uci.name(), threads, hash);
+
+            if uci.has_option("Threads") {
+                uci.set_option("Threads", &threads.to_string());
+            }
+
+            if uci.has_option("Hash") {
+                uci.set_option("Hash", &hash.max(1).to_string());
+            }
+        }
+    }
+}
+
+/// Splits a total into parts as evenly as possible, with every part getting at least 1
+/// The first parts get the remainder, so the opponent comes out ahead
+fn split(total :usize, parts :usize) -> Vec<usize> {
+    (0..parts).map(|part| {
+        (total / parts + if part < total % parts { 1 } else { 0 }).max(1)
+    }).collect()
+}
+
+/// The available memory in MB, from the contents of /proc/meminfo
+fn available_memory(meminfo :&str) -> Option<usize> {
+    let field = |name :&str| meminfo.lines()
+        .find(|line| line.starts_with(name))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<usize>().ok());
+
+    // older kernels don't have MemAvailable
+    field("MemAvailable:").or_else(|| field("MemFree:")).map(|kb| kb / 1024)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::Arc;
+
+    use crate::engine_manager::{split, available_memory, EngineManager};
+
+    #[test]
+    fn split_evenly() {
+        assert_eq!(vec![4, 4], split(8, 2));
+        assert_eq!(vec![3, 2, 2], split(7, 3));
+        assert_eq!(vec![1, 1], split(1, 2));
+        assert!(split(4, 0).is_empty());
+    }
+
+    #[test]
+    fn meminfo() {
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:         1093420 kB\nMemAvailable:    8388608 kB\nBuffers:          411936 kB\n";
+
+        assert_eq!(Some(8192), available_memory(meminfo));
+        assert_eq!(Some(1067), available_memory("MemTotal: 16318412 kB\nMemFree: 1093420 kB\n"));
+        assert_eq!(None, available_memory(""));
+    }
+
+    #[test]
+    fn stale_requests() {
+        let manager = EngineManager {
+            cores: 1,
+            hash_budget: 16,
+            engines: Vec::new(),
+            opponent_thinking: Arc::new(AtomicBool::new(false)),
+            opponent_request: Arc::new(AtomicUsize::new(0))
+        };
+
+        let first = manager.request_opponent_move();
+        assert!(manager.analysis_paused());
+        assert!(manager.is_current_request(first));
+
+        let second = manager.request_opponent_move();
+        assert!(!manager.is_current_request(first));
+        assert!(manager.is_current_request(second));
+
+        // with no engine thinking there's nothing to stop, but the request still goes stale
+        manager.set_opponent_thinking(false);
+        manager.cancel_opponent_move();
+        assert!(!manager.is_current_request(second));
+    }
+}
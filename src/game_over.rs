@@ -0,0 +1,113 @@
+use druid::widget::{Button, Flex, Label};
+use druid::{EventCtx, FileDialogOptions, MenuDesc, Widget, WidgetExt, WindowDesc, commands};
+use chess::{Color, GameResult};
+
+use crate::{State, PGN_FILE};
+use crate::draws::DrawReason;
+use crate::pgn::result_token;
+use crate::rating::white_score;
+
+/// The result's token & how the game ended
+/// Running out of time is recorded as resigning, so `flagged` tells them apart
+pub fn describe_result(result :GameResult, draw_reason :Option<DrawReason>, flagged :Option<Color>) -> String {
+    let reason = match (result, draw_reason) {
+        (GameResult::WhiteCheckmates, _) | (GameResult::BlackCheckmates, _) => "checkmate".to_string(),
+        (GameResult::WhiteResigns, _) | (GameResult::BlackResigns, _) if flagged.is_some() => format!("{:?} loses on time", flagged.unwrap()),
+        (GameResult::DrawDeclared, Some(reason)) if flagged.is_some() => format!("draw by {}, {:?} ran out of time", reason, flagged.unwrap()),
+        (GameResult::WhiteResigns, _) => "White resigns".to_string(),
+        (GameResult::BlackResigns, _) => "Black resigns".to_string(),
+        (GameResult::Stalemate, _) => "stalemate".to_string(),
+        (GameResult::DrawAccepted, _) => "draw agreed".to_string(),
+        (GameResult::DrawDeclared, Some(reason)) => format!("draw by {}", reason),
+        (GameResult::DrawDeclared, None) => "draw claimed".to_string()
+    };
+
+    format!("{} {}", result_token(Some(result)), reason)
+}
+
+/// The outcome from the human's side, who is always white
+fn headline(result :GameResult) -> &'static str {
+    match white_score(result) {
+        score if score > 0.5 => "You won!",
+        score if score < 0.5 => "You lost",
+        _ => "It's a draw"
+    }
+}
+
+/// Stops the clock, rates the game, and opens a window with the result, offering to start a new game, review this one, or save it
+pub fn show(ctx: &mut EventCtx, data: &mut State) {
+    let result = match data.result() {
+        Some(result) => result,
+        None => return
+    };
+
+    data.stop_clock();
+    data.rate_game();
+
+    let details = describe_result(result, data.draw_reason, data.flagged);
+
+    let dialog = WindowDesc::new(move || dialog(result, details))
+        .title("Game Over")
+        .window_size((320.0, 140.0))
+        .resizable(false)
+        .menu(MenuDesc::empty());
+
+    ctx.new_window(dialog);
+}
+
+fn dialog(result :GameResult, details :String) -> impl Widget<State> {
+    let new_game_button = Button::new("New Game")
+        .on_click(|ctx :&mut EventCtx, data: &mut State, _env| {
+            data.new_game();
+            ctx.window().close();
+        });
+
+    // leave the finished game on the board, to step through it
+    let review_button = Button::new("Review")
+        .on_click(|ctx :&mut EventCtx, _data: &mut State, _env| {
+            ctx.window().close();
+        });
+
+    let save_button = Button::new("Save…")
+        .on_click(|ctx :&mut EventCtx, _data: &mut State, _env| {
+            ctx.submit_command(commands::SHOW_SAVE_PANEL.with(FileDialogOptions::new().allowed_types(vec![PGN_FILE]).default_type(PGN_FILE)));
+        });
+
+    Flex::column()
+        .with_child(Label::new(headline(result)).with_text_size(20.0))
+        .with_spacer(5.0)
+        .with_child(Label::new(details))
+        .with_spacer(10.0)
+        .with_child(Flex::row()
+            .with_child(new_game_button)
+            .with_spacer(5.0)
+            .with_child(review_button)
+            .with_spacer(5.0)
+            .with_child(save_button))
+        .padding(10.0)
+        .center()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chess::{Color, GameResult};
+
+    use crate::draws::DrawReason;
+    use crate::game_over::{describe_result, headline};
+
+    #[test]
+    fn descriptions() {
+        assert_eq!("1-0 checkmate", describe_result(GameResult::WhiteCheckmates, None, None));
+        assert_eq!("0-1 White resigns", describe_result(GameResult::WhiteResigns, None, None));
+        assert_eq!("1/2-1/2 draw by threefold repetition", describe_result(GameResult::DrawDeclared, Some(DrawReason::Repetition), None));
+
+        // running out of time
+        assert_eq!("1-0 Black loses on time", describe_result(GameResult::BlackResigns, None, Some(Color::Black)));
+        assert_eq!("1/2-1/2 draw by insufficient material, White ran out of time",
+                   describe_result(GameResult::DrawDeclared, Some(DrawReason::InsufficientMaterial), Some(Color::White)));
+
+        assert_eq!("You lost", headline(GameResult::BlackCheckmates));
+        assert_eq!("It's a draw", headline(GameResult::Stalemate));
+    }
+}
@@ -0,0 +1,354 @@
+use chess::{Board, BoardStatus, ChessMove, Color, Game, GameResult};
+
+pub type NodeId = usize;
+
+/// A position in the tree, and the move that got there
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub board: Board,                   // the position after the move
+    pub chess_move: Option<ChessMove>,  // None for the starting position
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,          // the first child is the main line, the rest are variations
+    pub comments: Vec<String>,          // comments after the move
+    pub nags: Vec<u8>                   // numeric annotation glyphs, ex: 1 for !, 2 for ?
+}
+
+/// A game with all of its variations, and the node currently on the board
+/// Nodes are kept in a Vec and refer to each other by index, the root is the starting position
+/// Deleted nodes stay in the Vec, but nothing refers to them
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameTree {
+    nodes: Vec<Node>,
+    current: NodeId,
+    first_move: usize   // the move number in the starting position, from the FEN when there is one
+}
+
+impl Default for GameTree {
+    fn default() -> Self {
+        GameTree::new(Board::default())
+    }
+}
+
+impl GameTree {
+    pub fn new(start :Board) -> Self {
+        GameTree::new_numbered(start, 1)
+    }
+
+    /// A game starting from a position partway through, where the next move is number `first_move`
+    pub fn new_numbered(start :Board, first_move :usize) -> Self {
+        GameTree {
+            nodes: vec![Node { board: start, chess_move: None, parent: None, children: Vec::new(), comments: Vec::new(), nags: Vec::new() }],
+            current: 0,
+            first_move
+        }
+    }
+
+    /// The move number in the starting position
+    pub fn first_move(&self) -> usize {
+        self.first_move
+    }
+
+    /// The number of the move played from the position at `id`, ex: 1 for both 1. e4 and 1... e5
+    pub fn move_number(&self, id :NodeId) -> usize {
+        let black_first = if self.node(self.root()).board.side_to_move() == Color::Black { 1 } else { 0 };
+
+        (self.path(id).len() + black_first) / 2 + self.first_move
+    }
+
+    pub fn root(&self) -> NodeId {
+        0
+    }
+
+    pub fn node(&self, id :NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id :NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    /// The node on the board
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    pub fn select(&mut self, id :NodeId) {
+        self.current = id;
+    }
+
+    /// Takes the board back one move, returns false at the start of the game
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => { self.current = parent; true },
+            None => false
+        }
+    }
+
+    /// Plays the next move of the line on the board, returns false at the end of the line
+    pub fn forward(&mut self) -> bool {
+        match self.nodes[self.current].children.first() {
+            Some(child) => { self.current = *child; true },
+            None => false
+        }
+    }
+
+    /// Goes to the end of the line on the board
+    pub fn to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    /// The position on the board
+    pub fn current_position(&self) -> Board {
+        self.nodes[self.current].board
+    }
+
+    /// Plays the move from the position on the board
+    pub fn make_move(&mut self, chess_move :ChessMove) {
+        self.current = self.add_move(self.current, chess_move);
+    }
+
+    /// Plays the move from the node, reusing the child if the move was already played from there
+    pub fn add_move(&mut self, parent :NodeId, chess_move :ChessMove) -> NodeId {
+        if let Some(child) = self.nodes[parent].children.iter().find(|child| self.nodes[**child].chess_move == Some(chess_move)) {
+            return *child;
+        }
+
+        let id = self.nodes.len();
+        let board = self.nodes[parent].board.make_move_new(chess_move);
+
+        self.nodes.push(Node { board, chess_move: Some(chess_move), parent: Some(parent), children: Vec::new(), comments: Vec::new(), nags: Vec::new() });
+        self.nodes[parent].children.push(id);
+
+        id
+    }
+
+    /// The nodes from the start of the game to the given one, not including the root
+    pub fn path(&self, id :NodeId) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut id = id;
+
+        while let Some(parent) = self.nodes[id].parent {
+            path.push(id);
+            id = parent;
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// The number of moves played to get to the position on the board
+    pub fn ply(&self) -> usize {
+        self.path(self.current).len()
+    }
+
+    /// The nodes of the main line, after the root
+    pub fn mainline(&self) -> Vec<NodeId> {
+        self.continuation(self.root())
+    }
+
+    /// The line through the position on the board: the moves to get there, then its main line
+    pub fn line(&self) -> Vec<NodeId> {
+        let mut line = self.path(self.current);
+        line.extend(self.continuation(self.current));
+        line
+    }
+
+    /// Follows the first children from the node
+    fn continuation(&self, id :NodeId) -> Vec<NodeId> {
+        let mut line = Vec::new();
+        let mut id = id;
+
+        while let Some(child) = self.nodes[id].children.first() {
+            line.push(*child);
+            id = *child;
+        }
+
+        line
+    }
+
+    /// The moves to the position on the board as a chess::Game, for the engines
+    pub fn to_game(&self) -> Game {
+        self.to_game_at(self.current)
+    }
+
+    /// The moves to the node's position as a chess::Game
+    pub fn to_game_at(&self, id :NodeId) -> Game {
+        let mut game = Game::new_with_board(self.nodes[self.root()].board);
+
+        for id in self.path(id) {
+            game.make_move(self.nodes[id].chess_move.unwrap());
+        }
+
+        game
+    }
+
+    /// The result, if the position on the board ends the game
+    pub fn result(&self) -> Option<GameResult> {
+        self.result_at(self.current)
+    }
+
+    /// The result, if the node's position ends the game
+    pub fn result_at(&self, id :NodeId) -> Option<GameResult> {
+        let board = self.nodes[id].board;
+
+        match board.status() {
+            BoardStatus::Checkmate if board.side_to_move() == Color::White => Some(GameResult::BlackCheckmates),
+            BoardStatus::Checkmate => Some(GameResult::WhiteCheckmates),
+            BoardStatus::Stalemate => Some(GameResult::Stalemate),
+            BoardStatus::Ongoing => None
+        }
+    }
+
+    /// Moves the variation the node is in up one level, swapping it with the line it branches from
+    pub fn promote(&mut self, id :NodeId) {
+        let mut id = id;
+
+        while let Some(parent) = self.nodes[id].parent {
+            let children = &mut self.nodes[parent].children;
+            let index = children.iter().position(|child| *child == id).unwrap();
+
+            if index != 0 {
+                children.swap(0, index);
+                return;
+            }
+
+            id = parent;
+        }
+    }
+
+    /// Removes every move after the position on the board
+    pub fn truncate(&mut self) {
+        for child in std::mem::take(&mut self.nodes[self.current].children) {
+            self.nodes[child].parent = None;
+        }
+    }
+
+    /// Removes the node and everything after it, the board goes back to its parent if it was in there
+    pub fn delete(&mut self, id :NodeId) {
+        let parent = match self.nodes[id].parent {
+            Some(parent) => parent,
+            None => return  // can't delete the starting position
+        };
+
+        if self.path(self.current).contains(&id) {
+            self.current = parent;
+        }
+
+        self.nodes[parent].children.retain(|child| *child != id);
+        self.nodes[id].parent = None;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chess::{ChessMove, GameResult};
+
+    use crate::game_tree::GameTree;
+
+    fn mv(text :&str) -> ChessMove {
+        ChessMove::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn variations() {
+        let mut tree = GameTree::default();
+
+        tree.make_move(mv("e2e4"));
+        tree.make_move(mv("e7e5"));
+        let e5 = tree.current();
+
+        // go back and try something else
+        tree.select(tree.node(e5).parent.unwrap());
+        tree.make_move(mv("c7c5"));
+        let c5 = tree.current();
+
+        assert_eq!(2, tree.ply());
+        assert_eq!(vec![e5], tree.mainline()[1..].to_vec());
+        assert_eq!(c5, *tree.line().last().unwrap());
+
+        // playing the same move again goes to the same node
+        tree.select(tree.node(e5).parent.unwrap());
+        tree.make_move(mv("c7c5"));
+        assert_eq!(c5, tree.current());
+
+        assert_eq!(2, tree.to_game().actions().len());
+        assert_eq!(tree.current_position(), tree.to_game().current_position());
+    }
+
+    #[test]
+    fn promote_and_delete() {
+        let mut tree = GameTree::default();
+
+        tree.make_move(mv("e2e4"));
+        let e4 = tree.current();
+        tree.make_move(mv("e7e5"));
+        tree.make_move(mv("g1f3"));
+
+        tree.select(e4);
+        tree.make_move(mv("c7c5"));
+        tree.make_move(mv("g1f3"));
+        let sicilian_nf3 = tree.current();
+
+        // promoting from deep inside the variation swaps it with the main line
+        tree.promote(sicilian_nf3);
+        assert_eq!(sicilian_nf3, *tree.mainline().last().unwrap());
+
+        // deleting the line on the board puts the board on its parent
+        let c5 = tree.node(sicilian_nf3).parent.unwrap();
+        tree.delete(c5);
+
+        assert_eq!(e4, tree.current());
+        assert_eq!(3, tree.mainline().len());
+        assert_eq!(1, tree.node(e4).children.len());
+    }
+
+    #[test]
+    fn navigation() {
+        let mut tree = GameTree::default();
+
+        assert!(!tree.back());
+        assert!(!tree.forward());
+
+        for text in ["e2e4", "e7e5", "g1f3"].iter() {
+            tree.make_move(mv(text));
+        }
+
+        let end = tree.current();
+
+        assert!(tree.back());
+        assert!(tree.back());
+        assert_eq!(1, tree.ply());
+
+        assert!(tree.forward());
+        assert_eq!(2, tree.ply());
+
+        tree.select(tree.root());
+        tree.to_end();
+        assert_eq!(end, tree.current());
+
+        // playing over the history drops the old moves
+        tree.back();
+        tree.truncate();
+        tree.make_move(mv("b1c3"));
+
+        assert_eq!(3, tree.mainline().len());
+        assert_eq!(1, tree.node(tree.node(tree.current()).parent.unwrap()).children.len());
+    }
+
+    #[test]
+    fn result() {
+        let mut tree = GameTree::default();
+
+        for text in ["f2f3", "e7e5", "g2g4", "d8h4"].iter() {
+            tree.make_move(mv(text));
+        }
+
+        assert_eq!(Some(GameResult::BlackCheckmates), tree.result());
+
+        tree.select(tree.root());
+        assert_eq!(None, tree.result());
+    }
+}
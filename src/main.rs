@@ -1,93 +1,581 @@
 use std::default::Default;
 
 use druid::widget::prelude::*;
-use druid::widget::{Align, Flex, Label, Container, Split, List, Scroll, Controller, Button, Checkbox};
-use druid::{AppLauncher, Color, Data, MenuDesc, MenuItem, WindowDesc, WidgetExt, WindowState, Lens, UnitPoint, Selector, Target};
+use druid::widget::{Align, Flex, Label, Container, Split, ViewSwitcher, Controller, Button, Checkbox, Stepper, RadioGroup};
+use druid::{AppLauncher, Color, Data, MenuDesc, MenuItem, WindowDesc, WidgetExt, WindowState, Lens, UnitPoint, Selector, Target, ExtEventSink};
+use druid::{AppDelegate, DelegateCtx, Command, Handled, FileDialogOptions, FileSpec, LocalizedString, SysMods, commands};
 
 // use log::{debug, info};
-use chess::{Game, Action};
+use log::{debug, error};
+use chess::{Board, GameResult};
 
 mod board_widget;
 mod uci;
 mod chess_utils;
+mod analysis_cache;
+mod analysis_store;
+mod config;
+mod polyglot;
+mod tablebase;
+mod difficulty;
+mod rating;
+mod bench;
+mod engine_manager;
+mod consensus;
+mod pgn;
+mod game_tree;
+mod move_list;
+mod draws;
+mod game_over;
+mod clock;
+mod clock_widget;
 
 use board_widget::BoardWidget;
-use druid::im::Vector;
-use std::process::{Command, Stdio};
-use crate::uci::Uci;
+use crate::engine_manager::EngineManager;
+use crate::consensus::Consensus;
+use crate::pgn::PgnGame;
+use crate::game_tree::{GameTree, NodeId};
+use crate::move_list::FollowCurrent;
+use crate::draws::DrawReason;
+use crate::clock::{Bonus, Clock, TimeControl};
+use crate::clock_widget::ClockWidget;
+use crate::chess_utils::{NotationStyle, Language};
+use crate::polyglot::{PolyglotBook, BookSelection};
+use crate::tablebase::Tablebase;
+use crate::difficulty::{Difficulty, DEFAULT_LEVEL};
+use crate::rating::{Rating, ENGINE_DEVIATION, white_score};
 use std::sync::Arc;
+use std::thread;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
 
 
 #[derive(Debug, Clone, Lens)]
 pub struct State {
-    game: Game,     // state of our chess game
-    engine: Uci,    // engine the human is playing against
+    game: GameTree, // state of our chess game, with its variations
+    engines: EngineManager,  // the engine the human is playing against, and the analysis engines
+    engine_difficulty: Difficulty,  // the level the engine is playing the current game at
+    difficulty: usize,  // the level selected for the next game
+    adaptive_difficulty: bool,  // should the level follow the player's rating?
+    rating: Rating,     // the player's rating, updated after every finished game
+    rated: bool,        // the game already counted toward the rating, loaded games never do
+    book: Option<Arc<PolyglotBook>>,  // opening book the engine plays from, if we have one
+    book_selection: BookSelection,    // how the engine picks between book moves
+    max_book_depth: usize,            // number of moves into the game the book is used
     show_pieces_being_attacked: bool,  // should we show pieces being attacked
     disallow_blunders: bool, // should we prevent the user from making a blunder?
+    show_threat: bool,  // should we show what the opponent would play if it were their move?
+    analysis: String,   // text shown in the analysis area
+    hints_used: usize,  // number of hints asked for in the current game
+    takebacks: usize,   // number of moves taken back in the current game
+    ending: Option<GameResult>, // a resignation or draw, or a reply that ends the game while an earlier position is shown
+    loaded_tags: Option<Arc<Vec<(String, String)>>>,  // the tags of a game loaded from PGN, written back out when it's saved
+    draw_reason: Option<DrawReason>,    // why the game was drawn, or could be claimed as one
+    flagged: Option<chess::Color>,      // the side that ran out of time, if that ended the game
+    use_clock: bool,            // play the next game on the clock
+    time_control: TimeControl,  // the clock settings for the next game
+    clock: Option<Clock>,       // the current game's clock, untimed games don't have one
+    consensus: Option<Arc<Consensus>>,  // each analysis engine's lines, shown side by side
+    notation: NotationStyle,    // how moves are written in the move list & analysis
+    overwrite_history: bool,    // moves played from an earlier position replace the rest of the game, instead of starting a variation
 }
 
 impl Data for State {
     fn same(&self, other: &Self) -> bool {
-        self.game.current_position().combined() == other.game.current_position().combined() &&
+        self.game == other.game &&
             self.show_pieces_being_attacked == other.show_pieces_being_attacked &&
-            self.disallow_blunders == other.disallow_blunders
+            self.disallow_blunders == other.disallow_blunders &&
+            self.show_threat == other.show_threat &&
+            self.analysis == other.analysis &&
+            self.difficulty == other.difficulty &&
+            self.book_selection == other.book_selection &&
+            self.max_book_depth == other.max_book_depth &&
+            self.adaptive_difficulty == other.adaptive_difficulty &&
+            self.rating == other.rating &&
+            self.rated == other.rated &&
+            self.hints_used == other.hints_used &&
+            self.takebacks == other.takebacks &&
+            self.ending == other.ending &&
+            self.loaded_tags == other.loaded_tags &&
+            self.draw_reason == other.draw_reason &&
+            self.flagged == other.flagged &&
+            self.use_clock == other.use_clock &&
+            self.time_control == other.time_control &&
+            self.clock == other.clock &&
+            self.consensus.same(&other.consensus) &&
+            self.notation == other.notation &&
+            self.overwrite_history == other.overwrite_history
     }
 }
 
 impl State {
     fn new() -> Self {
-        // setup an engine to play against
-        let mut engine_cmd = Command::new("/usr/games/stockfish");
-        let mut engine = Uci::start_engine(&mut engine_cmd);
+        // setup an engine to play against, and one for analysis
+        let mut engines = EngineManager::new();
+
+        // the player's rating from previous sessions
+        let rating = match Rating::load(&config::data_dir().join("rating.txt")) {
+            Ok(rating) => rating,
+            Err(e) => { debug!("No saved rating: {:?}", e); Rating::default() }
+        };
+
+        // set the strength options for the starting level
+        let engine_difficulty = Difficulty::for_level(DEFAULT_LEVEL);
+        engine_difficulty.apply(&mut engines.opponent());
+
+        // let the engines use the endgame tablebases too
+        if let Ok(tablebase) = Tablebase::open(&config::data_dir().join("syzygy")) {
+            engines.set_syzygy_path(tablebase.path());
+        }
 
-        // set options to match lichess level 3
-        // see: https://lichess.org/blog/U4mtoEQAAEEAgZRL/strongest-chess-player-ever
-        engine.set_option("Skill Level", "9");
+        // use an opening book, so the engine doesn't have to think from move one
+        let book = match PolyglotBook::open(&config::data_dir().join("book.bin")) {
+            Ok(book) => Some(Arc::new(book)),
+            Err(e) => { debug!("No opening book: {:?}", e); None }
+        };
 
         State {
-            game: Game::new(),
-            engine,
+            game: GameTree::default(),
+            engines,
+            engine_difficulty,
+            difficulty: DEFAULT_LEVEL,
+            adaptive_difficulty: false,
+            rating,
+            rated: false,
+            book,
+            book_selection: BookSelection::WeightedRandom,
+            max_book_depth: 10,
             show_pieces_being_attacked: true,
-            disallow_blunders: true
+            disallow_blunders: true,
+            show_threat: false,
+            analysis: String::new(),
+            hints_used: 0,
+            takebacks: 0,
+            ending: None,
+            loaded_tags: None,
+            draw_reason: None,
+            flagged: None,
+            use_clock: false,
+            time_control: TimeControl { base: Duration::from_secs(5 * 60), increment: Duration::from_secs(3), bonus: Bonus::Fischer },
+            clock: None,
+            consensus: None,
+            notation: NotationStyle::San,
+            overwrite_history: false
         }
     }
-}
 
-struct MoveList;
-
-impl Lens<State, Vector<String>> for MoveList {
-    fn with<V, F: FnOnce(&Vector<String>) -> V>(&self, data: &State, f: F) -> V {
-        // convert the list of actions into strings
-        // TODO: add move numbers as well
-        let move_list :Vector<String> = data.game.actions().chunks(2).enumerate().map(|(num, actions)| {
-            let a1 = match actions[0] {
-                Action::MakeMove(chess_move) => { format!("{}: {}", num+1, chess_move)}
-                Action::Resign(color) => { format!("{}: {:?} resigns", num+1, color)}
-                _ => unimplemented!("Cannot convert draws to moves")
-            };
-
-            if actions.len() == 2 {
-                let a2 = match actions[1] {
-                    Action::MakeMove(chess_move) => { format!("{}", chess_move)}
-                    Action::Resign(color) => { format!("{:?} resigns", color)}
-                    _ => unimplemented!("Cannot convert draws to moves")
-                };
-
-                format!("{} {}", a1, a2)
-            } else {
-                a1
+    /// Starts a new game, with the engine playing at the selected difficulty
+    fn new_game(&mut self) {
+        self.game = GameTree::default();
+        self.rated = false;
+        self.analysis.clear();
+        self.hints_used = 0;
+        self.takebacks = 0;
+        self.ending = None;
+        self.loaded_tags = None;
+        self.draw_reason = None;
+        self.flagged = None;
+        self.consensus = None;
+
+        // white's clock starts right away
+        self.clock = if self.use_clock { Some(Clock::new(self.time_control)) } else { None };
+        self.start_clock(chess::Color::White);
+
+        self.engines.cancel_opponent_move();
+        self.engines.opponent().new_game();
+
+        if self.engine_difficulty.level != self.difficulty {
+            self.engine_difficulty = Difficulty::for_level(self.difficulty);
+            self.engine_difficulty.apply(&mut self.engines.opponent());
+        }
+    }
+
+    /// Rates the finished game against the level the engine played at, once per game
+    /// The human is always white
+    fn rate_game(&mut self) {
+        let result = match self.result() {
+            Some(result) if !self.rated => result,
+            _ => return
+        };
+
+        self.rated = true;
+        self.rating.update(&[(self.engine_difficulty.elo as f64, ENGINE_DEVIATION, white_score(result))]);
+
+        if let Err(e) = self.rating.save(&config::data_dir().join("rating.txt")) {
+            error!("Error saving rating: {:?}", e);
+        }
+
+        if self.adaptive_difficulty {
+            self.difficulty = Difficulty::for_rating(self.rating.rating).level;
+        }
+    }
+
+    /// How the game ended, by resignation, draw, or on the board
+    fn result(&self) -> Option<GameResult> {
+        self.ending.or_else(|| self.game.result())
+    }
+
+    /// Ends the game with the human resigning
+    /// Returns false if the game was already over
+    fn resign(&mut self) -> bool {
+        if self.result().is_some() {
+            return false;
+        }
+
+        self.engines.cancel_opponent_move();
+        self.ending = Some(GameResult::WhiteResigns);
+
+        true
+    }
+
+    /// Asks the opponent for a draw, which it takes unless it thinks it's winning
+    /// It thinks it over in the background, the answer comes back to the board as a DRAW_OFFER command
+    fn offer_draw(&mut self, event_sink :ExtEventSink) {
+        if self.result().is_some() {
+            return;
+        }
+
+        let mut uci = self.engines.analysis();
+        let game = self.game.to_game();
+        let node = self.game.current();
+
+        self.analysis = format!("{} is considering the draw", self.engines.opponent().name());
+
+        thread::spawn(move || {
+            let accepted = draws::accepts_draw(&mut uci, &game, chess::Color::Black);
+
+            if let Err(e) = event_sink.submit_command(board_widget::DRAW_OFFER, Box::new((node, accepted)), Target::Global) {
+                error!("Error submitting draw answer: {:?}", e);
+            }
+        });
+    }
+
+    /// The opponent's answer to a draw offered after the move at `node`
+    /// The offer is noted on that move, so it shows up in the PGN
+    /// An answer for a move that was taken back, or isn't on the board any more, is dropped
+    /// Returns true if the opponent accepted, ending the game
+    fn draw_offer_answered(&mut self, node :NodeId, accepted :bool) -> bool {
+        let current = self.game.current();
+
+        if self.result().is_some() || (node != current && !self.game.path(current).contains(&node)) {
+            return false;
+        }
+
+        if accepted {
+            self.engines.cancel_opponent_move();
+            self.ending = Some(GameResult::DrawAccepted);
+            self.analysis.clear();
+            self.game.node_mut(node).comments.push("White offers a draw, accepted".to_string());
+        } else {
+            self.analysis = format!("{} declines the draw", self.engines.opponent().name());
+            self.game.node_mut(node).comments.push("White offers a draw, declined".to_string());
+        }
+
+        accepted
+    }
+
+    /// Ends the game in a draw, if threefold repetition or the fifty-move rule allow it
+    /// Returns true if the claim ended the game
+    fn claim_draw(&mut self) -> bool {
+        if self.result().is_some() {
+            return false;
+        }
+
+        match draws::detect(&self.game, self.game.current()) {
+            Some(reason) if !reason.automatic() => {
+                self.engines.cancel_opponent_move();
+                self.ending = Some(GameResult::DrawDeclared);
+                self.draw_reason = Some(reason);
+                true
+            },
+            _ => {
+                self.analysis = "No draw to claim: that needs threefold repetition or 50 moves without a capture or pawn move".to_string();
+                false
+            }
+        }
+    }
+
+    /// Looks for a draw after the move at `node`: insufficient material ends the game, as stalemate already does,
+    /// and repetition or the fifty-move rule can be claimed
+    fn check_for_draw(&mut self, node :NodeId) {
+        self.draw_reason = draws::detect(&self.game, node);
+
+        let note = match self.draw_reason {
+            Some(DrawReason::InsufficientMaterial) => {
+                self.engines.cancel_opponent_move();
+                self.ending = Some(GameResult::DrawDeclared);
+                format!("Draw by {}", DrawReason::InsufficientMaterial)
+            },
+            Some(reason) if reason.automatic() => format!("Draw by {}", reason),
+            Some(reason) => format!("A draw by {} can be claimed", reason),
+            None => return
+        };
+
+        if !self.analysis.is_empty() {
+            self.analysis.push('\n');
+        }
+
+        self.analysis.push_str(&note);
+    }
+
+    /// A draw the opponent could claim on its turn, by repetition or the fifty-move rule
+    fn claimable_draw(&self) -> Option<DrawReason> {
+        self.draw_reason.filter(|reason| !reason.automatic() && self.result().is_none())
+    }
+
+    /// On its turn, the opponent claims any draw it's allowed to unless it thinks it's winning
+    /// It thinks it over in the background, the decision comes back to the board as a DRAW_CLAIM command
+    fn consider_claiming_draw(&self, request :usize, reason :DrawReason, event_sink :ExtEventSink) {
+        let mut uci = self.engines.analysis();
+        let game = self.game.to_game();
+        let node = self.game.current();
+
+        thread::spawn(move || {
+            let claim = Some(reason).filter(|_| draws::accepts_draw(&mut uci, &game, chess::Color::Black));
+
+            if let Err(e) = event_sink.submit_command(board_widget::DRAW_CLAIM, Box::new((request, node, claim)), Target::Global) {
+                error!("Error submitting draw claim: {:?}", e);
+            }
+        });
+    }
+
+    /// The opponent claims a draw, ending the game
+    fn opponent_claims_draw(&mut self, reason :DrawReason) {
+        self.engines.set_opponent_thinking(false);
+        self.ending = Some(GameResult::DrawDeclared);
+        self.draw_reason = Some(reason);
+        self.analysis = format!("{} claims a draw by {}", self.engines.opponent().name(), reason);
+    }
+
+    /// Takes back the human's last move at the end of the line being played, along with the opponent's reply,
+    /// and puts the position before it on the board, even if an earlier position was being looked at
+    /// An opponent still thinking about its reply is stopped
+    /// Returns false if there's no move of the human's to take back, or the game is over, as it's already been rated
+    fn take_back(&mut self) -> bool {
+        if self.result().is_some() {
+            return false;
+        }
+
+        // the human is always white, so on black's turn the last move is theirs
+        let last = self.game.line().last().copied().unwrap_or_else(|| self.game.root());
+        let human_move = if self.game.node(last).board.side_to_move() == chess::Color::Black {
+            Some(last)
+        } else {
+            self.game.node(last).parent
+        };
+
+        // the starting position isn't a move
+        let human_move = match human_move.filter(|id| self.game.node(*id).parent.is_some()) {
+            Some(id) => id,
+            None => return false
+        };
+
+        let before = self.game.node(human_move).parent.unwrap();
+        let plies = self.game.path(last).len() - self.game.path(before).len();
+
+        self.engines.cancel_opponent_move();
+        self.game.delete(human_move);
+        self.game.select(before);
+        self.takebacks += 1;
+        self.draw_reason = None;
+
+        // the human's clock runs again, without the bonus for the moves taken back
+        if let Some(clock) = self.clock.as_mut() {
+            clock.take_back(plies, chess::Color::White, Instant::now());
+        }
+
+        true
+    }
+
+    /// Is a game against the opponent going on? Finished & loaded games are only being looked over
+    fn playing(&self) -> bool {
+        self.result().is_none() && self.loaded_tags.is_none()
+    }
+
+    /// Removes the move on the board and everything after it, and puts the position before it on the board
+    /// During a game moves are taken back instead, so they're counted and the clock is put right
+    /// Returns false if there's nothing to delete
+    fn delete_move(&mut self) -> bool {
+        let current = self.game.current();
+
+        if self.game.node(current).parent.is_none() {
+            return false;
+        }
+
+        if self.playing() {
+            self.analysis = "Moves can't be deleted during a game, take them back instead".to_string();
+            return false;
+        }
+
+        // a reply still being searched for would land on the deleted move
+        self.engines.cancel_opponent_move();
+        self.game.delete(current);
+
+        true
+    }
+
+    /// Starts a side's clock, in timed games
+    fn start_clock(&mut self, color :chess::Color) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start(color, Instant::now());
+        }
+    }
+
+    /// The side to move finished its move, so its bonus is added and the other side's clock starts
+    fn press_clock(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.press(Instant::now());
+        }
+    }
+
+    /// Stops the clock once the game is over
+    fn stop_clock(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop(Instant::now());
+        }
+    }
+
+    /// A side ran out of time: they lose, unless the other side couldn't ever checkmate
+    fn flag_fall(&mut self, color :chess::Color) {
+        self.engines.cancel_opponent_move();
+        self.flagged = Some(color);
+
+        if !draws::can_checkmate(&self.game.current_position(), !color) {
+            self.ending = Some(GameResult::DrawDeclared);
+            self.draw_reason = Some(DrawReason::InsufficientMaterial);
+        } else if color == chess::Color::White {
+            self.ending = Some(GameResult::WhiteResigns);
+        } else {
+            self.ending = Some(GameResult::BlackResigns);
+        }
+    }
+
+    /// Searches the current position with every analysis engine in the background
+    /// The results come back to the board as a CONSENSUS command
+    fn compare_engines(&self, event_sink :ExtEventSink) {
+        if self.engines.analysis_paused() {
+            return;
+        }
+
+        let engines = self.engines.analysis_engines();
+        let game = self.game.to_game();
+
+        thread::spawn(move || {
+            let consensus = consensus::compare(engines, &game, consensus::CONSENSUS_DEPTH);
+
+            if let Err(e) = event_sink.submit_command(board_widget::CONSENSUS, Box::new(consensus), Target::Global) {
+                error!("Error submitting consensus: {:?}", e);
             }
-        }).collect();
+        });
+    }
+}
 
-        f(&move_list)
+impl State {
+    /// The game in PGN, with the engine's name & level as black
+    fn pgn(&self) -> String {
+        // a loaded game keeps its own players, date, etc, only the result can have changed
+        if let Some(loaded_tags) = self.loaded_tags.as_ref() {
+            let result = pgn::result_token(self.result()).to_string();
+            let mut tags = loaded_tags.iter().filter(|(name, _value)| name != "Result").cloned().collect::<Vec<_>>();
+            let position = loaded_tags.iter().position(|(name, _value)| name == "Result").unwrap_or(tags.len());
+
+            tags.insert(position, ("Result".to_string(), result));
+
+            return pgn::write_game(&tags, &self.game);
+        }
+
+        let player = std::env::var("USER").unwrap_or_else(|_| "Player".to_string());
+        let engine = format!("{} (level {}, skill {})", self.engines.opponent().name(), self.engine_difficulty.level, self.engine_difficulty.skill_level);
+
+        let tags = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "CGIR".to_string()),
+            ("Date", pgn::date(SystemTime::now())),
+            ("Round", "-".to_string()),
+            ("White", player),
+            ("Black", engine),
+            ("Result", pgn::result_token(self.result()).to_string()),
+            ("WhiteElo", format!("{:.0}", self.rating.rating)),
+            ("BlackElo", self.engine_difficulty.elo.to_string()),
+            ("Hints", self.hints_used.to_string()),
+            ("Takebacks", self.takebacks.to_string())
+        ];
+
+        // games loaded from a position say where they started
+        let root = self.game.node(self.game.root()).board;
+        let setup = if root != Board::default() || self.game.first_move() != 1 {
+            // the board doesn't keep the move number, so it comes from the game
+            let position = root.to_string().split_whitespace().take(5).collect::<Vec<_>>().join(" ");
+
+            vec![("SetUp", "1".to_string()), ("FEN", format!("{} {}", position, self.game.first_move()))]
+        } else {
+            vec![]
+        };
+
+        pgn::write_game(&tags.into_iter().chain(setup).map(|(name, value)| (name.to_string(), value)).collect::<Vec<_>>(), &self.game)
     }
 
-    fn with_mut<V, F: FnOnce(&mut Vector<String>) -> V>(&self, data: &mut State, f: F) -> V {
-        f(&mut Vector::new())
+    /// Puts a game read from PGN on the board, at the end of its main line
+    fn load_game(&mut self, pgn_game :&PgnGame) {
+        let mut tree = pgn_game.tree.clone();
+
+        if let Some(last) = tree.mainline().last() {
+            tree.select(*last);
+        }
+
+        self.game = tree;
+        self.rated = true;  // the game wasn't played here, so it never changes the rating
+        self.analysis.clear();
+        self.hints_used = 0;
+        self.takebacks = 0;
+        self.ending = pgn_game.tag("Result").and_then(pgn::parse_result_token);
+        self.loaded_tags = Some(Arc::new(pgn_game.tags.clone()));
+        self.draw_reason = None;
+        self.flagged = None;
+        self.consensus = None;
+        self.clock = None;
+
+        self.engines.cancel_opponent_move();
+        self.engines.opponent().new_game();
+    }
+}
+
+impl Data for NotationStyle {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for BookSelection {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for TimeControl {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for Bonus {
+    fn same(&self, other: &Self) -> bool {
+        self == other
     }
 }
 
 pub fn main() {
+    // benchmark an engine instead of starting the GUI: cgir bench [engine] [depth]
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if args.get(1).map(String::as_str) == Some("bench") {
+        let engine_path = args.get(2).map(String::as_str).unwrap_or("/usr/games/stockfish");
+        let depth = args.get(3).and_then(|depth| depth.parse().ok()).unwrap_or(bench::DEFAULT_DEPTH);
+
+        bench::run_command(engine_path, depth);
+        return;
+    }
+
     // create a default state
     let state = State::new();
 
@@ -98,30 +586,28 @@ pub fn main() {
         .title("CGIR - Chess GUI in Rust");
 
     AppLauncher::with_window(main_window)
+        .delegate(Delegate)
         .use_simple_logger()
         .launch(state)
         .expect("launch failed");
 }
 
 fn ui_builder() -> impl Widget<State> {
-    let ply_list = Scroll::new(List::new(|| {
-        Label::new(|chess_move :&String, _env: &_| chess_move.clone())
-            .align_vertical(UnitPoint::LEFT)
-            .padding(7.0)
-            .expand()
-            .height(25.0)
-            .background(Color::BLACK)
-    }).lens(MoveList))
-        .vertical()
-        .align_vertical(UnitPoint::TOP_LEFT)
-        ;
+    let ply_list = FollowCurrent::new()
+        .align_vertical(UnitPoint::TOP_LEFT);
 
     let bw = BoardWidget::new();
 
+    // the human plays white from the bottom, so black's clock goes on top
+    let board_layout = Flex::column()
+        .with_child(ClockWidget::new(chess::Color::Black))
+        .with_flex_child(Align::centered(bw), 1.0)
+        .with_child(ClockWidget::new(chess::Color::White));
+
     // this holds the top 2 splits: board | Plys
     let top_container = Container::new(
         Split::columns(
-            Align::centered(bw),
+            board_layout,
             Align::centered(ply_list)
         ).draggable(true)
     );
@@ -140,16 +626,185 @@ fn ui_builder() -> impl Widget<State> {
         })
         .lens(State::disallow_blunders);
 
+    // toggle showing the opponent's threat
+    let threat_checkbox = Checkbox::new("Show Threat")
+        .lens(State::show_threat);
+
+    // moves played from an earlier position either start a variation, or replace what came after
+    let overwrite_checkbox = Checkbox::new("Overwrite History")
+        .lens(State::overwrite_history);
+
+    // pick the difficulty for the next game
+    let difficulty_layout = Flex::row()
+        .with_child(Label::dynamic(|level :&usize, _env| format!("Difficulty: {}", level)))
+        .with_child(Stepper::new()
+            .with_range(1.0, difficulty::LEVELS.len() as f64)
+            .with_step(1.0)
+            .lens(druid::lens::Map::new(|level :&usize| *level as f64, |level :&mut usize, value :f64| *level = value as usize)))
+        .lens(State::difficulty);
+
+    // the clock for the next game: minutes each, then seconds of increment or delay per move
+    let clock_checkbox = Checkbox::new("Use Clock")
+        .lens(State::use_clock);
+
+    let time_control_layout = Flex::row()
+        .with_child(Label::dynamic(|time_control :&TimeControl, _env| format!("{}+{}", time_control.base.as_secs() / 60, time_control.increment.as_secs())))
+        .with_child(Stepper::new()
+            .with_range(1.0, 180.0)
+            .with_step(1.0)
+            .lens(druid::lens::Map::new(
+                |time_control :&TimeControl| (time_control.base.as_secs() / 60) as f64,
+                |time_control :&mut TimeControl, minutes :f64| time_control.base = Duration::from_secs(minutes as u64 * 60))))
+        .with_child(Stepper::new()
+            .with_range(0.0, 60.0)
+            .with_step(1.0)
+            .lens(druid::lens::Map::new(
+                |time_control :&TimeControl| time_control.increment.as_secs() as f64,
+                |time_control :&mut TimeControl, seconds :f64| time_control.increment = Duration::from_secs(seconds as u64))))
+        .with_child(RadioGroup::new(vec![
+            ("Fischer", Bonus::Fischer),
+            ("Bronstein", Bonus::Bronstein),
+            ("Delay", Bonus::Delay)
+        ]).lens(druid::lens::Map::new(
+            |time_control :&TimeControl| time_control.bonus,
+            |time_control :&mut TimeControl, bonus :Bonus| time_control.bonus = bonus)))
+        .lens(State::time_control);
+
+    // how the engine plays from the opening book, and for how many moves
+    let book_layout = Flex::row()
+        .with_child(RadioGroup::new(vec![
+            ("Weighted Random", BookSelection::WeightedRandom),
+            ("Best Move", BookSelection::BestMove)
+        ]).lens(State::book_selection))
+        .with_child(Label::dynamic(|depth :&usize, _env| format!("Book depth: {}", depth)).lens(State::max_book_depth))
+        .with_child(Stepper::new()
+            .with_range(0.0, 30.0)
+            .with_step(1.0)
+            .lens(druid::lens::Map::new(|depth :&usize| *depth as f64, |depth :&mut usize, value :f64| *depth = value as usize))
+            .lens(State::max_book_depth));
+
+    // let the difficulty follow the player's rating
+    let adaptive_checkbox = Checkbox::new("Adaptive Difficulty")
+        .lens(State::adaptive_difficulty);
+
+    let rating_label = Label::dynamic(|data :&State, _env| format!("Rating: {:.0} ± {:.0}", data.rating.rating, data.rating.deviation));
+
+    // start over, using the selected difficulty
+    let new_game_button = Button::new("New Game")
+        .on_click(|_ctx :&mut EventCtx, data: &mut State, _env| {
+            data.new_game();
+        });
+
+    // ask the analysis engine for a move, pressing again shows more of it
+    let hint_button = Button::new("Hint")
+        .on_click(|ctx :&mut EventCtx, _data: &mut State, _env| {
+            ctx.submit_command(board_widget::HINT);
+        });
+
+    let hints_label = Label::dynamic(|hints_used :&usize, _env| format!("Hints used: {}", hints_used))
+        .lens(State::hints_used);
+
+    // undo a misclick, the opponent's reply goes too
+    let takeback_button = Button::new("Take Back")
+        .on_click(|ctx :&mut EventCtx, _data: &mut State, _env| {
+            ctx.submit_command(board_widget::TAKEBACK);
+        });
+
+    let takebacks_label = Label::dynamic(|takebacks :&usize, _env| format!("Takebacks: {}", takebacks))
+        .lens(State::takebacks);
+
+    // end the game early, the opponent decides on draw offers
+    let offer_draw_button = Button::new("Offer Draw")
+        .on_click(|ctx :&mut EventCtx, data: &mut State, _env| {
+            data.offer_draw(ctx.get_external_handle());
+        });
+
+    let claim_draw_button = Button::new("Claim Draw")
+        .on_click(|ctx :&mut EventCtx, data: &mut State, _env| {
+            if data.claim_draw() {
+                game_over::show(ctx, data);
+            }
+        });
+
+    let resign_button = Button::new("Resign")
+        .on_click(|ctx :&mut EventCtx, data: &mut State, _env| {
+            if data.resign() {
+                game_over::show(ctx, data);
+            }
+        });
+
+    // how moves are written
+    let notation_radio = RadioGroup::new(vec![
+        ("SAN", NotationStyle::San),
+        ("Figurine", NotationStyle::Figurine),
+        ("Long Algebraic", NotationStyle::Long),
+        ("UCI", NotationStyle::Uci),
+        ("German", NotationStyle::Localized(Language::German)),
+        ("French", NotationStyle::Localized(Language::French)),
+        ("Spanish", NotationStyle::Localized(Language::Spanish)),
+        ("Italian", NotationStyle::Localized(Language::Italian)),
+        ("Dutch", NotationStyle::Localized(Language::Dutch))
+    ]).lens(State::notation);
+
+    // search the position with all the analysis engines, to compare their lines
+    let compare_button = Button::new("Compare Engines")
+        .on_click(|ctx :&mut EventCtx, data: &mut State, _env| {
+            data.consensus = None;
+            data.compare_engines(ctx.get_external_handle());
+        });
+
+    // make the line on the board the main line, one level at a time
+    let promote_button = Button::new("Promote Variation")
+        .on_click(|_ctx :&mut EventCtx, data: &mut State, _env| {
+            let current = data.game.current();
+            data.game.promote(current);
+        });
+
+    // remove the move on the board and everything after it
+    let delete_button = Button::new("Delete Move")
+        .on_click(|ctx :&mut EventCtx, _data: &mut State, _env| {
+            ctx.submit_command(board_widget::DELETE_MOVE);
+        });
+
     // build the Flex container for the bottom analysis section
     let checkbox_layout = Flex::column()
         .with_child(Align::left(attacker_checkbox))
         .with_child(Align::left(blunder_checkbox))
+        .with_child(Align::left(threat_checkbox))
+        .with_child(Align::left(overwrite_checkbox))
+        .with_child(Align::left(difficulty_layout))
+        .with_child(Align::left(adaptive_checkbox))
+        .with_child(Align::left(Label::new("Opening Book")))
+        .with_child(Align::left(book_layout))
+        .with_child(Align::left(clock_checkbox))
+        .with_child(Align::left(time_control_layout))
+        .with_child(Align::left(rating_label))
+        .with_child(Align::left(Flex::row().with_child(hint_button).with_child(hints_label)))
+        .with_child(Align::left(Flex::row().with_child(takeback_button).with_child(takebacks_label)))
+        .with_child(Align::left(compare_button))
+        .with_child(Align::left(Label::new("Notation")))
+        .with_child(Align::left(notation_radio))
+        .with_child(Align::left(Flex::row().with_child(promote_button).with_child(delete_button)))
+        .with_child(Align::left(Flex::row().with_child(offer_draw_button).with_child(claim_draw_button).with_child(resign_button)))
+        .with_child(Align::left(new_game_button))
         .align_left()
         ;
 
     let analysis_container = Container::new(
         Split::columns(
-            Align::left(Label::new("Analysis")),
+            Flex::column()
+                .with_child(Align::left(Label::dynamic(|analysis :&String, _env| analysis.clone()).lens(State::analysis)))
+                .with_child(Align::left(ViewSwitcher::new(
+                    |data :&State, _env| (data.consensus.clone(), data.notation),
+                    |(consensus, notation), _data, _env| {
+                        // each analysis engine's lines from the last comparison, side by side
+                        let columns = consensus.as_ref().map(|consensus| consensus.columns(*notation)).unwrap_or_default();
+
+                        columns.into_iter().fold(Flex::row(), |row, lines| row.with_child(Label::new(lines).padding(7.0))).boxed()
+                    })))
+                .with_child(Align::left(Label::dynamic(|data :&State, _env| {
+                    data.consensus.as_ref().map(|consensus| consensus.disagreements(data.notation).join("\n")).unwrap_or_default()
+                }).with_text_color(Color::rgb8(0xff, 0xa5, 0x00)))),
             checkbox_layout
         ).draggable(false)
             .solid_bar(true)
@@ -166,19 +821,71 @@ fn ui_builder() -> impl Widget<State> {
     window_container
 }
 
+/// Handles the commands from the menus
+struct Delegate;
+
+impl AppDelegate<State> for Delegate {
+    fn command(&mut self, _ctx: &mut DelegateCtx, _target: Target, cmd: &Command, data: &mut State, _env: &Env) -> Handled {
+        if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            if let Err(e) = fs::write(file_info.path(), data.pgn()) {
+                error!("Error saving {}: {:?}", file_info.path().display(), e);
+            }
+
+            return Handled::Yes;
+        }
+
+        // load the first game in the file
+        if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
+            let games = fs::read_to_string(file_info.path())
+                .map_err(|e| e.to_string())
+                .and_then(|text| pgn::read_games(&text).map_err(|e| e.to_string()));
+
+            match games {
+                Ok(games) if !games.is_empty() => data.load_game(&games[0]),
+                Ok(_) => error!("No games in {}", file_info.path().display()),
+                Err(e) => error!("Error opening {}: {}", file_info.path().display(), e)
+            }
+
+            return Handled::Yes;
+        }
+
+        Handled::No
+    }
+}
+
+const PGN_FILE :FileSpec = FileSpec::new("PGN", &["pgn"]);
+
 #[allow(unused_assignments)]
-fn make_menu<T: Data>(_state: &State) -> MenuDesc<T> {
+fn make_menu(_state: &State) -> MenuDesc<State> {
     let mut base = MenuDesc::empty();
     #[cfg(target_os = "macos")]
     {
-        base = druid::platform_menus::mac::menu_bar();
+        base = base.append(druid::platform_menus::mac::application::default());
     }
 
+    // open & save games as PGN
+    let mut file_menu = MenuDesc::new(LocalizedString::new("common-menu-file-menu"))
+        .append(MenuItem::new(
+            LocalizedString::new("common-menu-file-open"),
+            commands::SHOW_OPEN_PANEL.with(FileDialogOptions::new().allowed_types(vec![PGN_FILE]))
+        ).hotkey(SysMods::Cmd, "o"))
+        .append(MenuItem::new(
+            LocalizedString::new("common-menu-file-save-ellipsis"),
+            commands::SHOW_SAVE_PANEL.with(FileDialogOptions::new().allowed_types(vec![PGN_FILE]).default_type(PGN_FILE))
+        ).hotkey(SysMods::Cmd, "s"));
+
     #[cfg(any(target_os = "windows", target_os = "linux"))]
     {
-        base = base.append(druid::platform_menus::win::file::default());
+        file_menu = file_menu
+            .append_separator()
+            .append(druid::platform_menus::win::file::exit());
     }
 
-    base
-}
+    let edit_menu = MenuDesc::new(LocalizedString::new("common-menu-edit-menu"))
+        .append(MenuItem::new(
+            LocalizedString::new("cgir-menu-takeback").with_placeholder("Take Back"),
+            board_widget::TAKEBACK
+        ).hotkey(SysMods::Cmd, "z"));
 
+    base.append(file_menu).append(edit_menu)
+}
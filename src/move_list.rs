@@ -0,0 +1,232 @@
+use druid::{Widget, EventCtx, LifeCycle, PaintCtx, LifeCycleCtx, BoxConstraints, Size, LayoutCtx, Event, Env, UpdateCtx, Point, Rect, Color, TextLayout};
+use druid::RenderContext;
+use druid::widget::Scroll;
+
+use crate::State;
+use crate::board_widget::SELECT_NODE;
+use crate::chess_utils::to_styled_notation;
+use crate::game_tree::NodeId;
+use crate::game_over::describe_result;
+
+const ROW_HEIGHT :f64 = 25.0;
+const NUMBER_WIDTH :f64 = 40.0;    // the move numbers' column, white's & black's columns split the rest
+const PADDING :f64 = 7.0;
+const DEFAULT_WIDTH :f64 = 250.0;  // if we're given all the width we want
+const HIGHLIGHT :Color = Color::rgb8(0x30, 0x50, 0x80);
+
+/// Where a move goes in the list: its row, and 0 for white's column or 1 for black's
+/// When black moves first, white's half of the first row is left empty
+fn cell(index :usize, black_first :bool) -> (usize, usize) {
+    let index = index + if black_first { 1 } else { 0 };
+
+    (index / 2, index % 2)
+}
+
+/// The moves through the position on the board, numbered in two columns
+/// The move on the board is highlighted, and clicking a move puts its position on the board
+pub struct MoveList {
+    moves: Vec<(NodeId, String)>,   // each move written in the selected notation
+    result: Option<String>,         // once the game is over, shown after the moves
+    black_first: bool,
+    first_move: usize,              // the number of the first row, games loaded from a FEN can start partway through
+    column_width: f64
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: Vec::new(),
+            result: None,
+            black_first: false,
+            first_move: 1,
+            column_width: 0.0
+        }
+    }
+
+    /// Writes out the line on the board, each move from the position before it
+    fn rebuild(&mut self, data: &State) {
+        let game = &data.game;
+
+        self.black_first = game.node(game.root()).board.side_to_move() == chess::Color::Black;
+        self.first_move = game.first_move();
+        self.moves = game.line().into_iter().map(|id| {
+            let node = game.node(id);
+            let board = game.node(node.parent.unwrap()).board;
+
+            (id, to_styled_notation(&node.chess_move.unwrap(), &board, data.notation))
+        }).collect();
+
+        // the game ends at the end of the line, or when someone resigns or a draw is agreed
+        let last = self.moves.last().map_or(game.root(), |(id, _notation)| *id);
+        self.result = data.ending.or_else(|| game.result_at(last)).map(|result| describe_result(result, data.draw_reason, data.flagged));
+    }
+
+    /// The rows of moves, not counting the result
+    fn rows(&self) -> usize {
+        self.moves.len().checked_sub(1).map_or(0, |last| cell(last, self.black_first).0 + 1)
+    }
+
+    /// The rectangle of a move in the list
+    fn rect(&self, index :usize) -> Rect {
+        let (row, column) = cell(index, self.black_first);
+
+        Rect::from_origin_size(
+            (NUMBER_WIDTH + column as f64 * self.column_width, row as f64 * ROW_HEIGHT),
+            (self.column_width, ROW_HEIGHT))
+    }
+
+    /// The rectangle of the move on the board, if it's in the list
+    fn current_rect(&self, data: &State) -> Option<Rect> {
+        self.moves.iter().position(|(id, _notation)| *id == data.game.current()).map(|index| self.rect(index))
+    }
+
+    /// The move under a point in the list
+    fn point2move(&self, point :&Point) -> Option<NodeId> {
+        if point.x < NUMBER_WIDTH || self.column_width <= 0.0 {
+            return None;
+        }
+
+        let row = (point.y / ROW_HEIGHT) as usize;
+        let column = (((point.x - NUMBER_WIDTH) / self.column_width) as usize).min(1);
+
+        (row * 2 + column).checked_sub(if self.black_first { 1 } else { 0 })
+            .and_then(|index| self.moves.get(index))
+            .map(|(id, _notation)| *id)
+    }
+}
+
+impl Widget<State> for MoveList {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut State, _env: &Env) {
+        if let Event::MouseDown(mouse_event) = event {
+            if let Some(id) = self.point2move(&mouse_event.pos) {
+                ctx.submit_command(SELECT_NODE.with(id));
+                ctx.set_handled();
+            }
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &State, _env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild(data);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &State, data: &State, _env: &Env) {
+        if old_data.game != data.game || old_data.notation != data.notation || old_data.ending != data.ending || old_data.draw_reason != data.draw_reason || old_data.flagged != data.flagged {
+            self.rebuild(data);
+            ctx.request_layout();
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &State, _env: &Env) -> Size {
+        let width = if bc.is_width_bounded() { bc.max().width } else { DEFAULT_WIDTH };
+        self.column_width = (width - NUMBER_WIDTH) / 2.0;
+
+        let rows = self.rows() + if self.result.is_some() { 1 } else { 0 };
+
+        bc.constrain(Size::new(width, rows as f64 * ROW_HEIGHT))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &State, env: &Env) {
+        let size = ctx.size();
+        ctx.fill(size.to_rect(), &Color::BLACK);
+
+        let draw_text = |ctx: &mut PaintCtx, text :String, color :Color, rect :Rect| {
+            let mut label = TextLayout::<String>::from_text(text);
+            label.set_text_color(color);
+
+            label.rebuild_if_needed(ctx.text(), env);
+            label.draw(ctx, Point::new(rect.x0 + PADDING, rect.y0 + (ROW_HEIGHT - label.size().height) / 2.0));
+        };
+
+        for row in 0..self.rows() {
+            let rect = Rect::from_origin_size((0.0, row as f64 * ROW_HEIGHT), (NUMBER_WIDTH, ROW_HEIGHT));
+            draw_text(ctx, format!("{}.", row + self.first_move), Color::grey(0.6), rect);
+        }
+
+        for (index, (id, notation)) in self.moves.iter().enumerate() {
+            let rect = self.rect(index);
+
+            if *id == data.game.current() {
+                ctx.fill(rect, &HIGHLIGHT);
+            }
+
+            draw_text(ctx, notation.clone(), Color::WHITE, rect);
+        }
+
+        if let Some(result) = self.result.as_ref() {
+            let rect = Rect::from_origin_size((NUMBER_WIDTH, self.rows() as f64 * ROW_HEIGHT), (size.width - NUMBER_WIDTH, ROW_HEIGHT));
+            draw_text(ctx, result.clone(), Color::WHITE, rect);
+        }
+    }
+}
+
+/// Keeps the move on the board scrolled into view, so the latest move is shown as the game goes on
+pub struct FollowCurrent {
+    scroll: Scroll<State, MoveList>,
+    follow: bool    // scroll once the list has been laid out
+}
+
+impl FollowCurrent {
+    pub fn new() -> Self {
+        FollowCurrent {
+            scroll: Scroll::new(MoveList::new()).vertical(),
+            follow: true
+        }
+    }
+}
+
+impl Widget<State> for FollowCurrent {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut State, env: &Env) {
+        self.scroll.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &State, env: &Env) {
+        self.scroll.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &State, data: &State, env: &Env) {
+        self.scroll.update(ctx, old_data, data, env);
+
+        if old_data.game != data.game {
+            self.follow = true;
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &State, env: &Env) -> Size {
+        let size = self.scroll.layout(ctx, bc, data, env);
+
+        if self.follow {
+            if let Some(rect) = self.scroll.child().current_rect(data) {
+                self.scroll.scroll_to(rect);
+            }
+
+            self.follow = false;
+        }
+
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &State, env: &Env) {
+        self.scroll.paint(ctx, data, env);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::move_list::cell;
+
+    #[test]
+    fn cells() {
+        assert_eq!((0, 0), cell(0, false));
+        assert_eq!((0, 1), cell(1, false));
+        assert_eq!((1, 0), cell(2, false));
+
+        // black's first move goes next to an empty cell for white
+        assert_eq!((0, 1), cell(0, true));
+        assert_eq!((1, 0), cell(1, true));
+    }
+}
@@ -0,0 +1,546 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chess::{Board, Color, GameResult};
+
+use crate::chess_utils::{to_notation, from_notation, NotationError};
+use crate::game_tree::{GameTree, NodeId};
+
+const LINE_WIDTH :usize = 80;   // the export format's limit for movetext lines
+
+/// The tags every PGN game has, in the order they're written
+pub const SEVEN_TAG_ROSTER :[&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// The token for the Result tag and the end of the movetext, * while the game is going
+pub fn result_token(result :Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::WhiteCheckmates) | Some(GameResult::BlackResigns) => "1-0",
+        Some(GameResult::BlackCheckmates) | Some(GameResult::WhiteResigns) => "0-1",
+        Some(GameResult::Stalemate) | Some(GameResult::DrawAccepted) | Some(GameResult::DrawDeclared) => "1/2-1/2",
+        None => "*"
+    }
+}
+
+/// The result for a Result tag's token, PGN doesn't say how a game was won so wins are resignations
+/// None while the game is going, or for an unknown token
+pub fn parse_result_token(token :&str) -> Option<GameResult> {
+    match token {
+        "1-0" => Some(GameResult::BlackResigns),
+        "0-1" => Some(GameResult::WhiteResigns),
+        "1/2-1/2" => Some(GameResult::DrawAccepted),
+        _ => None
+    }
+}
+
+/// Writes the game in PGN export format, with its variations, comments, and NAGs
+/// The tags are written in the order given, so the Seven Tag Roster should come first
+/// The movetext ends with the Result tag's token
+pub fn write_game(tags :&[(String, String)], tree :&GameTree) -> String {
+    let mut pgn = String::new();
+
+    for (name, value) in tags.iter() {
+        pgn += format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")).as_str();
+    }
+
+    pgn += "\n";
+
+    let mut tokens = Vec::new();
+    let root = tree.node(tree.root());
+
+    // the first move always has its number, even when black starts
+    write_comments(&root.comments, &mut tokens);
+    write_line(tree, tree.root(), &mut tokens, true);
+
+    let result = tags.iter().find(|(name, _value)| name == "Result").map_or("*", |(_name, value)| value.as_str());
+    tokens.push(result.to_string());
+
+    // wrap the movetext, only ever breaking between tokens
+    let mut line = String::new();
+
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+            pgn += line.as_str();
+            pgn += "\n";
+            line.clear();
+        }
+
+        if !line.is_empty() {
+            line += " ";
+        }
+
+        line += token.as_str();
+    }
+
+    pgn += line.as_str();
+    pgn += "\n";
+
+    pgn
+}
+
+/// Writes the moves after the node, each variation in parentheses after the move it replaces
+fn write_line(tree :&GameTree, parent :NodeId, tokens :&mut Vec<String>, number :bool) {
+    let (main, variations) = match tree.node(parent).children.split_first() {
+        Some(children) => children,
+        None => return
+    };
+
+    let mut number = write_move(tree, *main, tokens, number);
+
+    for variation in variations {
+        let mut line = Vec::new();
+        let variation_number = write_move(tree, *variation, &mut line, true);
+        write_line(tree, *variation, &mut line, variation_number);
+
+        line[0].insert(0, '(');
+        line.last_mut().unwrap().push(')');
+        tokens.extend(line);
+
+        // the move after a variation needs its number again
+        number = true;
+    }
+
+    write_line(tree, *main, tokens, number);
+}
+
+/// Writes the move in SAN, with its number when it's white's or when asked for
+/// Returns if the next move needs its number, because this one had comments
+fn write_move(tree :&GameTree, id :NodeId, tokens :&mut Vec<String>, number :bool) -> bool {
+    let node = tree.node(id);
+    let parent = node.parent.unwrap();
+    let board = tree.node(parent).board;
+
+    let move_number = tree.move_number(parent);
+
+    if board.side_to_move() == Color::White {
+        tokens.push(format!("{}.", move_number));
+    } else if number {
+        tokens.push(format!("{}...", move_number));
+    }
+
+    tokens.push(to_notation(&node.chess_move.unwrap(), &board));
+    tokens.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+    write_comments(&node.comments, tokens);
+
+    !node.comments.is_empty()
+}
+
+/// Comments are split into words, so the movetext can be wrapped inside them
+fn write_comments(comments :&[String], tokens :&mut Vec<String>) {
+    for comment in comments {
+        let mut words = comment.split_whitespace().map(|word| word.to_string()).collect::<Vec<_>>();
+
+        if words.is_empty() {
+            words.push(String::new());
+        }
+
+        words[0].insert(0, '{');
+        words.last_mut().unwrap().push('}');
+        tokens.extend(words);
+    }
+}
+
+/// The date as YYYY.MM.DD, in UTC
+pub fn date(time :SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() / 86_400).unwrap_or(0) as i64;
+
+    // converts days since 1970-01-01 to a civil date
+    // see: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// A game read from a PGN file
+#[derive(Clone, Debug)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub tree: GameTree
+}
+
+impl PgnGame {
+    pub fn tag(&self, name :&str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _value)| tag_name == name).map(|(_name, value)| value.as_str())
+    }
+}
+
+/// Why a PGN file couldn't be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    Syntax(String),                 // unbalanced brackets, unterminated strings, etc
+    BadFen(String),                 // the FEN tag isn't a valid position
+    BadMove(usize, NotationError)   // the ply (from the start of the line) of a move that can't be played
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f :&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Syntax(msg) => write!(f, "PGN syntax error: {}", msg),
+            PgnError::BadFen(fen) => write!(f, "Bad FEN tag: {}", fen),
+            PgnError::BadMove(ply, e) => write!(f, "Bad move at ply {}: {}", ply, e)
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Open,           // start of a variation
+    Close,          // end of a variation
+    Nag(u8),
+    Symbol(String)  // moves, move numbers, and results
+}
+
+/// Splits PGN text into tokens
+fn tokenize(text :&str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            // a % at the start of a line escapes the whole line
+            '%' if line_start => { while chars.next_if(|c| *c != '\n').is_some() {} },
+            '\n' => { line_start = true; continue },
+            c if c.is_whitespace() => (),
+            '[' => {
+                let name = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '"')).collect::<String>();
+
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+                if chars.next() != Some('"') {
+                    return Err(PgnError::Syntax(format!("Tag {} has no value", name)));
+                }
+
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('\\') => { value.extend(chars.next()); },
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(PgnError::Syntax(format!("Unterminated value for tag {}", name)))
+                    }
+                }
+
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+                if chars.next() != Some(']') {
+                    return Err(PgnError::Syntax(format!("Tag {} isn't closed", name)));
+                }
+
+                tokens.push(Token::Tag(name, value));
+            },
+            '{' => {
+                let comment = std::iter::from_fn(|| chars.next_if(|c| *c != '}')).collect::<String>();
+
+                if chars.next().is_none() {
+                    return Err(PgnError::Syntax("Unterminated comment".to_string()));
+                }
+
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            },
+            ';' => {
+                let comment = std::iter::from_fn(|| chars.next_if(|c| *c != '\n')).collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            },
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let nag = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect::<String>();
+                tokens.push(Token::Nag(nag.parse().map_err(|_| PgnError::Syntax(format!("Bad NAG: ${}", nag)))?));
+            },
+            c => {
+                let symbol = std::iter::once(c)
+                    .chain(std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && !"[]{}();$".contains(*c))))
+                    .collect::<String>();
+
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+
+        line_start = false;
+    }
+
+    Ok(tokens)
+}
+
+/// The NAG for a suffix annotation like !?
+fn suffix_nag(suffix :&str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+const RESULTS :[&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Reads every game in the PGN text
+pub fn read_games(text :&str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+
+    while tokens.peek().is_some() {
+        // the tags come first
+        let mut tags = Vec::new();
+
+        while let Some(Token::Tag(name, value)) = tokens.peek().cloned() {
+            tags.push((name, value));
+            tokens.next();
+        }
+
+        // games can start from a position other than the initial one, numbered from its fullmove number
+        let mut tree = match tags.iter().find(|(name, _value)| name == "FEN") {
+            Some((_name, fen)) => {
+                let start = Board::from_str(fen).map_err(|_| PgnError::BadFen(fen.clone()))?;
+                let first_move = fen.split_whitespace().nth(5).and_then(|number| number.parse().ok()).filter(|number| *number > 0).unwrap_or(1);
+
+                GameTree::new_numbered(start, first_move)
+            },
+            None => GameTree::default()
+        };
+        let mut current = tree.root();
+        let mut variations = Vec::new();   // where to go back to when each variation ends
+
+        // then the movetext, until the result
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Tag(name, _value) => return Err(PgnError::Syntax(format!("Tag {} in the movetext", name))),
+                Token::Comment(comment) => tree.node_mut(current).comments.push(comment),
+                Token::Nag(nag) => tree.node_mut(current).nags.push(nag),
+                Token::Open => {
+                    // a variation replaces the last move
+                    let parent = tree.node(current).parent.ok_or_else(|| PgnError::Syntax("Variation before the first move".to_string()))?;
+
+                    variations.push(current);
+                    current = parent;
+                },
+                Token::Close => {
+                    current = variations.pop().ok_or_else(|| PgnError::Syntax("Unbalanced )".to_string()))?;
+                },
+                Token::Symbol(symbol) if RESULTS.contains(&symbol.as_str()) => {
+                    if !variations.is_empty() {
+                        return Err(PgnError::Syntax("Game ended inside a variation".to_string()));
+                    }
+
+                    break;
+                },
+                Token::Symbol(symbol) => {
+                    // skip over move numbers, which can be stuck to the move: 1.e4 or 1...e5
+                    let san = match symbol.rfind('.') {
+                        Some(dot) if symbol.starts_with(|c :char| c.is_ascii_digit()) => &symbol[dot + 1..],
+                        _ => symbol.as_str()
+                    };
+
+                    if san.is_empty() {
+                        continue;
+                    }
+
+                    // suffix annotations become NAGs
+                    let annotation_start = san.find(|c| c == '!' || c == '?').unwrap_or(san.len());
+                    let (san, suffix) = san.split_at(annotation_start);
+
+                    let board = tree.node(current).board;
+                    let chess_move = from_notation(san, &board).map_err(|e| PgnError::BadMove(tree.path(current).len() + 1, e))?;
+
+                    current = tree.add_move(current, chess_move);
+                    tree.node_mut(current).nags.extend(suffix_nag(suffix));
+                }
+            }
+        }
+
+        if !variations.is_empty() {
+            return Err(PgnError::Syntax("Unclosed variation".to_string()));
+        }
+
+        games.push(PgnGame { tags, tree });
+    }
+
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use chess::ChessMove;
+
+    use crate::game_tree::GameTree;
+    use crate::pgn::{date, parse_result_token, result_token, write_game, read_games, PgnError, SEVEN_TAG_ROSTER};
+    use crate::chess_utils::NotationError;
+
+    fn make_tags(result :&str) -> Vec<(String, String)> {
+        let values = ["Casual game", "CGIR", "2021.02.14", "-", "Player", "Stockfish 13 \"level 3\"", result];
+
+        SEVEN_TAG_ROSTER.iter().zip(values.iter()).map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn scholars_mate() {
+        let mut tree = GameTree::default();
+
+        for mv in ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"].iter() {
+            tree.make_move(ChessMove::from_str(mv).unwrap());
+        }
+
+        let pgn = write_game(&make_tags(result_token(tree.result())), &tree);
+
+        assert_eq!("[Event \"Casual game\"]
+[Site \"CGIR\"]
+[Date \"2021.02.14\"]
+[Round \"-\"]
+[White \"Player\"]
+[Black \"Stockfish 13 \\\"level 3\\\"\"]
+[Result \"1-0\"]
+
+1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0
+", pgn);
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut tree = GameTree::default();
+
+        // shuffle the knights back and forth
+        for _ in 0..10 {
+            for mv in ["g1f3", "g8f6", "f3g1", "f6g8"].iter() {
+                tree.make_move(ChessMove::from_str(mv).unwrap());
+            }
+        }
+
+        // black resigned
+        let pgn = write_game(&make_tags("1-0"), &tree);
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|line| line.len() <= 80));
+        assert!(movetext.starts_with("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3"));
+        assert!(movetext.trim_end().ends_with("20. Ng1 Ng8 1-0"));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!("1970.01.01", date(UNIX_EPOCH));
+        assert_eq!("2021.02.14", date(UNIX_EPOCH + Duration::from_secs(1_613_300_000)));
+        assert_eq!("2000.02.29", date(UNIX_EPOCH + Duration::from_secs(951_782_400)));
+    }
+
+    #[test]
+    fn result_tokens() {
+        for token in ["1-0", "0-1", "1/2-1/2"].iter() {
+            assert_eq!(*token, result_token(parse_result_token(token)));
+        }
+
+        assert_eq!(None, parse_result_token("*"));
+    }
+
+    #[test]
+    fn read_with_variations() {
+        let text = r#"[Event "Casual game"]
+[White "Player"]
+[Black "Stockfish 13"]
+[Result "1-0"]
+
+{Scholar's mate} 1. e4 e5 2. Bc4 (2. Nf3 Nc6 (2... d6 $6) 3. Bb5) 2... Nc6
+; black should defend f7
+3. Qh5 Nf6?? $18 4.Qxf7# 1-0
+"#;
+
+        let games = read_games(text).unwrap();
+        assert_eq!(1, games.len());
+
+        let game = &games[0];
+        let tree = &game.tree;
+
+        assert_eq!(Some("Stockfish 13"), game.tag("Black"));
+        assert_eq!(vec!["Scholar's mate".to_string()], tree.node(tree.root()).comments);
+
+        let mainline = tree.mainline();
+        let moves = mainline.iter().map(|id| tree.node(*id).chess_move.unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"], moves);
+
+        // 2. Nf3 is a variation on 2. Bc4, and 2... d6 is a variation inside it
+        let after_e5 = tree.node(mainline[1]);
+        assert_eq!(2, after_e5.children.len());
+
+        let nf3 = tree.node(after_e5.children[1]);
+        assert_eq!("g1f3", nf3.chess_move.unwrap().to_string());
+        assert_eq!(2, nf3.children.len());
+        assert_eq!(vec![6], tree.node(nf3.children[1]).nags);
+
+        // comments & NAGs go on the move before them
+        assert_eq!(vec!["black should defend f7".to_string()], tree.node(mainline[3]).comments);
+        assert_eq!(vec![4, 18], tree.node(mainline[5]).nags);
+
+        // writing it back out keeps the variations, comments, and NAGs
+        let pgn = write_game(&game.tags, tree);
+        assert!(pgn.contains("{Scholar's mate} 1. e4 e5 2. Bc4 (2. Nf3 Nc6 (2... d6 $6) 3. Bb5) 2... Nc6"));
+        assert!(pgn.contains("{black should defend f7} 3. Qh5 Nf6 $4 $18 4. Qxf7# 1-0"));
+        assert_eq!(*tree, read_games(&pgn).unwrap()[0].tree);
+    }
+
+    #[test]
+    fn read_from_fen() {
+        let text = r#"[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+
+1. e4 Kd7 *
+
+[Event "second"]
+
+1. d4 1/2-1/2
+"#;
+
+        let games = read_games(text).unwrap();
+        assert_eq!(2, games.len());
+
+        let tree = &games[0].tree;
+        assert_eq!(2, tree.mainline().len());
+        assert!(write_game(&[], tree).contains("1. e4 Kd7 *"));
+        assert_eq!(Some(chess::Piece::King), tree.node(tree.mainline()[1]).board.piece_on(chess::Square::D7));
+
+        assert_eq!(Some("second"), games[1].tag("Event"));
+        assert_eq!(1, games[1].tree.mainline().len());
+    }
+
+    #[test]
+    fn numbered_from_fen() {
+        let text = r#"[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 3 30"]
+
+30... Kd7 31. e4 *
+"#;
+
+        let games = read_games(text).unwrap();
+        let tree = &games[0].tree;
+
+        assert_eq!(30, tree.first_move());
+        assert!(write_game(&[], tree).contains("30... Kd7 31. e4 *"));
+    }
+
+    #[test]
+    fn read_errors() {
+        assert_eq!(Err(PgnError::BadMove(3, NotationError::Illegal("Ke3".to_string()))), read_games("1. e4 e5 2. Ke3 *").map(|_| ()));
+        assert_eq!(Err(PgnError::BadFen("nonsense".to_string())), read_games("[FEN \"nonsense\"]\n\n*").map(|_| ()));
+        assert!(matches!(read_games("1. e4 (1. d4 *"), Err(PgnError::Syntax(_))));
+        assert!(matches!(read_games("1. e4 { never closed"), Err(PgnError::Syntax(_))));
+        assert!(matches!(read_games("1. e4 e5 )"), Err(PgnError::Syntax(_))));
+    }
+}
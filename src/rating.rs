@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chess::GameResult;
+
+const SCALE :f64 = 173.7178;    // converts between the Glicko and Glicko-2 scales
+const TAU :f64 = 0.5;           // constrains how much the volatility can change
+const EPSILON :f64 = 0.000_001; // convergence tolerance for the volatility
+
+/// How sure we are of the rating the engine plays at for a difficulty level
+pub const ENGINE_DEVIATION :f64 = 100.0;
+
+/// A Glicko-2 rating, kept on the Glicko scale (1500 is average)
+/// see: http://www.glicko.net/glicko/glicko2.pdf
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating { rating: 1500.0, deviation: 350.0, volatility: 0.06 }
+    }
+}
+
+impl Rating {
+    /// Loads the rating saved as: rating deviation volatility
+    pub fn load(path :&Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let values = contents.split_whitespace().map(|value| value.parse::<f64>()).collect::<Result<Vec<_>, _>>();
+
+        match values {
+            Ok(values) if values.len() == 3 => Ok(Rating { rating: values[0], deviation: values[1], volatility: values[2] }),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Bad rating in {}", path.display())))
+        }
+    }
+
+    pub fn save(&self, path :&Path) -> io::Result<()> {
+        fs::write(path, format!("{} {} {}\n", self.rating, self.deviation, self.volatility))
+    }
+
+    /// Updates the rating for a rating period
+    /// Each result is the opponent's rating & deviation, and our score: 1 for a win, 0.5 for a draw, 0 for a loss
+    pub fn update(&mut self, results :&[(f64, f64, f64)]) {
+        let mu = (self.rating - 1500.0) / SCALE;
+        let phi = self.deviation / SCALE;
+
+        // no games just makes us less sure of the rating
+        if results.is_empty() {
+            self.deviation = (phi * phi + self.volatility * self.volatility).sqrt() * SCALE;
+            return;
+        }
+
+        // the estimated variance, and the improvement over the expected scores
+        let mut variance_inv = 0.0;
+        let mut improvement = 0.0;
+
+        for (opp_rating, opp_deviation, score) in results {
+            let opp_mu = (opp_rating - 1500.0) / SCALE;
+            let g = g(opp_deviation / SCALE);
+            let expected = 1.0 / (1.0 + (-g * (mu - opp_mu)).exp());
+
+            variance_inv += g * g * expected * (1.0 - expected);
+            improvement += g * (score - expected);
+        }
+
+        let v = 1.0 / variance_inv;
+        let delta = v * improvement;
+
+        let volatility = self.new_volatility(phi, v, delta);
+
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+
+        self.rating = new_mu * SCALE + 1500.0;
+        self.deviation = new_phi * SCALE;
+        self.volatility = volatility;
+    }
+
+    /// Step 5 of the paper, finding the new volatility with the Illinois algorithm
+    fn new_volatility(&self, phi :f64, v :f64, delta :f64) -> f64 {
+        let a = (self.volatility * self.volatility).ln();
+        let f = |x :f64| {
+            let ex = x.exp();
+            let denom = phi * phi + v + ex;
+
+            (ex * (delta * delta - phi * phi - v - ex)) / (2.0 * denom * denom) - (x - a) / (TAU * TAU)
+        };
+
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+
+            a - k * TAU
+        };
+
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+
+        while (big_b - big_a).abs() > EPSILON {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+
+            big_b = big_c;
+            f_b = f_c;
+        }
+
+        (big_a / 2.0).exp()
+    }
+}
+
+fn g(phi :f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+/// White's score for a finished game
+pub fn white_score(result :GameResult) -> f64 {
+    match result {
+        GameResult::WhiteCheckmates | GameResult::BlackResigns => 1.0,
+        GameResult::BlackCheckmates | GameResult::WhiteResigns => 0.0,
+        GameResult::Stalemate | GameResult::DrawAccepted | GameResult::DrawDeclared => 0.5
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::rating::Rating;
+
+    #[test]
+    fn glickman_example() {
+        // the worked example from the Glicko-2 paper
+        let mut rating = Rating { rating: 1500.0, deviation: 200.0, volatility: 0.06 };
+
+        rating.update(&[(1400.0, 30.0, 1.0), (1550.0, 100.0, 0.0), (1700.0, 300.0, 0.0)]);
+
+        assert!((rating.rating - 1464.06).abs() < 0.01, "{}", rating.rating);
+        assert!((rating.deviation - 151.52).abs() < 0.01, "{}", rating.deviation);
+        assert!((rating.volatility - 0.05999).abs() < 0.0001, "{}", rating.volatility);
+    }
+
+    #[test]
+    fn no_games() {
+        let mut rating = Rating { rating: 1500.0, deviation: 200.0, volatility: 0.06 };
+
+        rating.update(&[]);
+
+        assert_eq!(1500.0, rating.rating);
+        assert!(rating.deviation > 200.0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chess::{Board, BoardStatus, ChessMove, Color, Piece};
+use log::debug;
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{AmbiguousWdl, Tablebase as Syzygy};
+
+use crate::chess_utils::{to_styled_notation, NotationStyle};
+
+/// Win/draw/loss from the point of view of the side to move, counting the fifty-move rule
+/// Cursed wins and blessed losses are wins and losses the fifty-move rule turns into draws,
+/// the maybe results are the ones the tables can't settle because DTZ is rounded for them
+pub type Wdl = AmbiguousWdl;
+
+/// The perfect-play result for a position
+#[derive(Clone, Debug)]
+pub struct TablebaseResult {
+    pub wdl: Wdl,
+    pub dtz: Option<i32>,               // plies to the next capture or pawn move with best play, None when the game is over
+    pub best_move: Option<ChessMove>    // None when the game is already over
+}
+
+impl TablebaseResult {
+    /// Describes the result for the analysis area
+    pub fn describe(&self, board :&Board, style :NotationStyle) -> String {
+        let side = if board.side_to_move() == Color::White { "White" } else { "Black" };
+        let other = if board.side_to_move() == Color::White { "Black" } else { "White" };
+
+        let result = match self.wdl {
+            Wdl::Win => format!("{} wins", side),
+            Wdl::MaybeWin => format!("{} wins, unless the fifty-move rule comes first", side),
+            Wdl::CursedWin => format!("Draw by the fifty-move rule, {} wins without it", side),
+            Wdl::Draw => "Draw".to_string(),
+            Wdl::BlessedLoss => format!("Draw by the fifty-move rule, {} wins without it", other),
+            Wdl::MaybeLoss => format!("{} wins, unless the fifty-move rule comes first", other),
+            Wdl::Loss => format!("{} wins", other)
+        };
+
+        match (self.best_move, self.dtz) {
+            (Some(mv), Some(dtz)) if dtz != 0 => format!("Tablebase: {}, DTZ {} (best: {})", result, dtz.abs(), to_styled_notation(&mv, board, style)),
+            (Some(mv), _) => format!("Tablebase: {} (best: {})", result, to_styled_notation(&mv, board, style)),
+            (None, _) => format!("Tablebase: {}", result)
+        }
+    }
+}
+
+/// Syzygy endgame tablebases on local disk, the WDL/DTZ files are read directly
+/// The directory is also handed to engines as SyzygyPath, so their searches use the same tables
+pub struct Tablebase {
+    path: PathBuf,
+    tables: Syzygy<Chess>
+}
+
+impl Tablebase {
+    /// Opens all the WDL (.rtbw) and DTZ (.rtbz) tables in the directory
+    pub fn open(path :&Path) -> io::Result<Self> {
+        let mut tables = Syzygy::new();
+        let count = tables.add_directory(path)?;
+
+        if count == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No Syzygy tables found"));
+        }
+
+        debug!("Found {} Syzygy tables, up to {} pieces, in {}", count, tables.max_pieces(), path.display());
+
+        Ok(Tablebase { path: path.to_path_buf(), tables })
+    }
+
+    /// The directory to pass to engines as SyzygyPath
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Looks up the perfect-play result for the position, `halfmoves` since the last capture or pawn move
+    /// None when the tables don't cover it: too many pieces, castling rights, or a missing table
+    pub fn probe(&self, board :&Board, halfmoves :usize) -> Option<TablebaseResult> {
+        match board.status() {
+            BoardStatus::Checkmate => return Some(TablebaseResult { wdl: Wdl::Loss, dtz: None, best_move: None }),
+            BoardStatus::Stalemate => return Some(TablebaseResult { wdl: Wdl::Draw, dtz: None, best_move: None }),
+            BoardStatus::Ongoing => ()
+        }
+
+        if board.combined().popcnt() as usize > self.tables.max_pieces() {
+            return None;
+        }
+
+        let position = to_position(board, halfmoves)?;
+
+        let wdl = self.tables.probe_wdl(&position).map_err(|e| debug!("Tablebase probe failed: {}", e)).ok()?;
+        let best = self.tables.best_move(&position).map_err(|e| debug!("Tablebase probe failed: {}", e)).ok()?;
+
+        let (dtz, best_move) = match best {
+            Some((mv, dtz)) => {
+                let uci = mv.to_uci(CastlingMode::Standard).to_string();
+
+                (Some(dtz.ignore_rounding().0), ChessMove::from_str(&uci).ok())
+            },
+            None => (None, None)
+        };
+
+        Some(TablebaseResult { wdl, dtz, best_move })
+    }
+
+    /// Checks if the move makes the result worse for the side making it, ex: a win into a draw
+    /// None if the tables don't cover the position before and after the move
+    pub fn is_blunder(&self, board :&Board, halfmoves :usize, chess_move :ChessMove) -> Option<bool> {
+        let after_board = board.make_move_new(chess_move);
+
+        // captures and pawn moves reset the fifty-move count
+        let zeroing = board.piece_on(chess_move.get_source()) == Some(Piece::Pawn) || board.piece_on(chess_move.get_dest()).is_some();
+        let after_halfmoves = if zeroing { 0 } else { halfmoves + 1 };
+
+        let before = self.probe(board, halfmoves)?;
+        let after = self.probe(&after_board, after_halfmoves)?;
+
+        debug!("TABLEBASE: {:?} -> {:?}", before.wdl, -after.wdl);
+
+        Some(-after.wdl < before.wdl)
+    }
+}
+
+/// The board as a shakmaty position, with the halfmove clock the chess crate doesn't keep
+fn to_position(board :&Board, halfmoves :usize) -> Option<Chess> {
+    let mut fields = board.to_string().split_whitespace().map(|field| field.to_string()).collect::<Vec<_>>();
+
+    if fields.len() < 5 {
+        return None;
+    }
+
+    fields[4] = halfmoves.to_string();
+
+    let fen = Fen::from_ascii(fields.join(" ").as_bytes()).ok()?;
+
+    fen.into_position(CastlingMode::Standard).ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chess::{Board, ChessMove};
+    use shakmaty::Position;
+
+    use crate::chess_utils::NotationStyle;
+    use crate::tablebase::{to_position, TablebaseResult, Wdl};
+
+    #[test]
+    fn positions() {
+        let board = Board::from_str("8/8/4k3/8/2r5/1P6/1RK5/8 w - - 0 1").unwrap();
+        let position = to_position(&board, 37).unwrap();
+
+        assert_eq!(37, position.halfmoves());
+        assert_eq!(5, position.board().occupied().count());
+    }
+
+    #[test]
+    fn wdl_order() {
+        assert!(Wdl::Loss < Wdl::BlessedLoss && Wdl::BlessedLoss < Wdl::Draw);
+        assert!(Wdl::Draw < Wdl::CursedWin && Wdl::CursedWin < Wdl::Win);
+        assert_eq!(Wdl::Loss, -Wdl::Win);
+        assert_eq!(Wdl::BlessedLoss, -Wdl::CursedWin);
+    }
+
+    #[test]
+    fn descriptions() {
+        let board = Board::from_str("8/8/4k3/8/2r5/1P6/1RK5/8 w - - 0 1").unwrap();
+        let best_move = Some(ChessMove::from_str("b2b1").unwrap());
+
+        let win = TablebaseResult { wdl: Wdl::Win, dtz: Some(13), best_move };
+        assert_eq!("Tablebase: White wins, DTZ 13 (best: Rb1)", win.describe(&board, NotationStyle::San));
+
+        let cursed = TablebaseResult { wdl: Wdl::BlessedLoss, dtz: Some(-120), best_move };
+        assert_eq!("Tablebase: Draw by the fifty-move rule, Black wins without it, DTZ 120 (best: Rb1)", cursed.describe(&board, NotationStyle::San));
+    }
+}
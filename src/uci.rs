@@ -2,14 +2,23 @@ use std::process::{Command, Stdio, ChildStdin, ChildStdout};
 use std::io::{BufReader, Write, BufRead};
 use std::thread;
 use std::sync::mpsc::{channel, Receiver};
-use std::sync::{Mutex, Arc};
-
-use log::{debug, warn};
-use vampirc_uci::{ByteVecUciMessage, UciMessage, parse_one, UciFen, UciSearchControl, UciTimeControl, UciInfoAttribute};
-use chess::{Game, ChessMove};
+use std::sync::{Mutex, MutexGuard, Arc, Condvar};
+use std::path::Path;
+use std::io;
+
+use log::{debug, warn, error};
+use vampirc_uci::{ByteVecUciMessage, UciMessage, parse_one, UciFen, UciSearchControl, UciTimeControl, UciInfoAttribute, UciOptionConfig};
+use chess::{Game, ChessMove, Board};
+use std::time::Duration;
 use std::collections::HashMap;
 use itertools::Itertools;
 
+use crate::analysis_cache::{AnalysisCache, CachedAnalysis};
+use crate::analysis_store::AnalysisStore;
+
+/// Mate scores are reported as this, less the number of moves to mate
+pub const MATE_SCORE :i32 = 100_000;
+
 #[derive(Clone, Debug)]
 pub enum Analysis {
     PossibleMove(PossibleMove),
@@ -19,16 +28,22 @@ pub enum Analysis {
 /// This is a candidate move given the depth
 #[derive(Clone, Default, Debug)]
 pub struct PossibleMove {
-    depth: u8,
-    score: i32,
-    multi_pv: u16,
-    moves: Vec<ChessMove>
+    pub depth: u8,
+    pub score: i32,
+    pub multi_pv: u16,
+    pub moves: Vec<ChessMove>,
+    pub nodes: u64      // nodes searched so far, not saved in the cache
 }
 
 #[derive(Debug, Clone)]
 pub struct Uci {
     stdin: Arc<Mutex<ChildStdin>>,
     stdout: Arc<Mutex<BufReader<ChildStdout>>>,
+    name: String,   // the name the engine reported with `id name`
+    options: Vec<UciOptionConfig>,  // the options the engine says it supports
+    multi_pv: u16,  // number of lines the engine reports, part of the cache key
+    cache: Arc<Mutex<AnalysisCache>>,   // finished searches, shared across clones
+    searching: Arc<(Mutex<bool>, Condvar)>,  // is a search running, signalled when its reader sees the end of it
 }
 
 impl Uci {
@@ -47,37 +62,31 @@ impl Uci {
         // init with the UCI message
         Self::send_msg(&mut stdin, UciMessage::Uci);
 
-        // we manually read because a lot of engines send non-UCI at first
-        let mut msg_buffer = String::new();
-
-        stdout.read_line(&mut msg_buffer).expect("Error reading");
+        // a lot of engines send non-UCI at first, which the reader skips over
+        let mut name = String::new();
+        let mut options = Vec::new();
 
-        while msg_buffer.find("id ").is_none() {
-            msg_buffer.clear();
-            stdout.read_line(&mut msg_buffer).expect("Error reading");
-        }
+        loop {
+            let message = Self::recv_msg(&mut stdout).expect("Error reading from engine");
 
-        // found the first id line
-        let start = msg_buffer.find("id ").unwrap();
-        let mut message = parse_one(&msg_buffer.as_str()[start..]);
+            debug!("MSG: {:?}", message);
 
-        loop {
-            println!("MSG: {:?}", message);
+            // save the name of the engine, and what options it has
+            match &message {
+                UciMessage::Id { name: Some(id_name), .. } => { name = id_name.clone(); },
+                UciMessage::Option(option) => { options.push(option.clone()); },
+                _ => ()
+            }
 
             // go until we get the OK
             if let UciMessage::UciOk = message {
                 break
             }
-
-            // keep reading messages
-            message = Self::recv_msg(&mut stdout) ;
         }
 
         // check to see if it's ready
         Self::send_msg(&mut stdin, UciMessage::IsReady);
-        message = Self::recv_msg(&mut stdout) ;
-
-        println!("MSG: {:?}", message);
+        let message = Self::recv_msg(&mut stdout).expect("Error reading from engine");
 
         if UciMessage::ReadyOk != message {
             panic!("Error setting up engine");
@@ -86,8 +95,7 @@ impl Uci {
         // let the engine we're staring a new game
         Self::send_msg(&mut stdin, UciMessage::UciNewGame);
 
-        // bump the number of threads so it works faster :-)
-        Self::send_msg(&mut stdin, UciMessage::SetOption {name: "Threads".to_string(), value: Some("4".to_string())});
+        // Threads & Hash are left to the EngineManager, which knows about all the engines
 
         // // also tell it to use analysis mode
         // Self::send_msg(&mut stdin, UciMessage::SetOption { name: "UCI_AnalyseMode".to_string(), value: Some("true".to_string()) });
@@ -97,54 +105,253 @@ impl Uci {
 
         // check to see if it's ready
         Self::send_msg(&mut stdin, UciMessage::IsReady);
-        message = Self::recv_msg(&mut stdout) ;
+        let message = Self::recv_msg(&mut stdout).expect("Error reading from engine");
 
         if let UciMessage::ReadyOk = message {
             Uci {
                 stdin: Arc::new(Mutex::new(stdin)),
-                stdout: Arc::new(Mutex::new(stdout))
+                stdout: Arc::new(Mutex::new(stdout)),
+                name,
+                options,
+                multi_pv: 1,
+                cache: Arc::new(Mutex::new(AnalysisCache::new())),
+                searching: Arc::new((Mutex::new(false), Condvar::new()))
             }
         } else {
             panic!("Error setting up engine");
         }
     }
 
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn has_option(&self, name :&str) -> bool {
+        self.options.iter().any(|option| option.get_name() == name)
+    }
+
+    /// The min & max of a spin option, if the engine has it
+    pub fn option_range(&self, name :&str) -> Option<(i64, i64)> {
+        self.options.iter().find_map(|option| match option {
+            UciOptionConfig::Spin { name: spin_name, min: Some(min), max: Some(max), .. } if spin_name == name => Some((*min, *max)),
+            _ => None
+        })
+    }
+
+    /// Lets the engine know the next search is from a different game
+    pub fn new_game(&mut self) {
+        let _idle = self.idle();
+        let mut stdin = self.stdin.lock().unwrap();
+        let mut stdout = self.stdout.lock().unwrap();
+
+        Self::send_msg(&mut stdin, UciMessage::UciNewGame);
+        Self::send_msg(&mut stdin, UciMessage::IsReady);
+
+        if UciMessage::ReadyOk != Self::recv_msg(&mut *stdout).expect("Error reading from engine") {
+            panic!("Error starting a new game")
+        }
+    }
+
+    /// Tells the engine to stop searching, and waits for the best move it found
+    /// The move still goes to the stopped search's Receiver, never to a later search's
+    pub fn stop(&mut self) {
+        drop(self.idle());
+    }
+
+    /// Opens the on-disk store at the given path, so searches are saved between sessions
+    pub fn open_store(&mut self, path :&Path) -> io::Result<()> {
+        let store = AnalysisStore::open(path, &self.name)?;
+
+        self.cache.lock().unwrap().set_store(store);
+
+        Ok(())
+    }
+
     pub fn set_option(&mut self, name :&str, value :&str) {
+        // the number of lines changes what a search returns, so track it for the cache
+        if name == "MultiPV" {
+            self.multi_pv = value.parse().unwrap_or(1);
+        }
+
+        let _idle = self.idle();
         let mut stdin = self.stdin.lock().unwrap();
         let mut stdout = self.stdout.lock().unwrap();
 
         // send the option message
-        Self::send_msg(&mut stdin, UciMessage::SetOption { name: name.to_string(), value: Some("4".to_string()) });
+        Self::send_msg(&mut stdin, UciMessage::SetOption { name: name.to_string(), value: Some(value.to_string()) });
 
         // check to see if it's ready
         Self::send_msg(&mut stdin, UciMessage::IsReady);
 
-        if UciMessage::ReadyOk != Self::recv_msg(&mut stdout) {
-            panic!("Error setting option")
+        // some options make the engine report back (ex: SyzygyPath), so skip over any info
+        loop {
+            match Self::recv_msg(&mut *stdout).expect("Error reading from engine") {
+                UciMessage::ReadyOk => break,
+                UciMessage::Info(attrs) => debug!("INFO: {:?}", attrs),
+                message => panic!("Error setting option: {:?}", message)
+            }
+        }
+    }
+
+    /// Stops the running search, if there is one, and waits for its reader to get the `bestmove`
+    /// The engine is only ever running one search, so no other can start until the guard is dropped
+    fn idle(&self) -> MutexGuard<'_, bool> {
+        let (lock, finished) = &*self.searching;
+        let mut searching = lock.lock().unwrap();
+
+        while *searching {
+            Self::send_msg(&mut self.stdin.lock().unwrap(), UciMessage::Stop);
+            searching = finished.wait(searching).unwrap();
         }
+
+        searching
     }
 
     fn send_msg(stdin :&mut ChildStdin, message :UciMessage) {
-        println!("MSG: {}", message.to_string());
+        debug!("MSG: {}", message);
         stdin.write_all(ByteVecUciMessage::from(message).as_ref()).expect("Error writing");
         stdin.flush().expect("Error flushing");
     }
 
-    fn recv_msg(stdout: &mut BufReader<ChildStdout>) -> UciMessage {
-        let mut buff = String::new();
+    /// Reads lines until there's a well-formed message, logging and skipping everything else:
+    /// empty lines, banners, unknown commands, and `info string` chatter
+    /// A `bestmove` without a move (ex: `bestmove (none)`) comes back as Unknown, as it still ends the search
+    /// An error is returned if the engine closes its output
+    fn recv_msg<R :BufRead>(stdout: &mut R) -> io::Result<UciMessage> {
+        let mut buff = Vec::new();
+
+        loop {
+            buff.clear();
+
+            // read bytes instead of a String, so a bad byte doesn't fail the whole line
+            if stdout.read_until(b'\n', &mut buff)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Engine closed its output"));
+            }
+
+            // handles both LF and CRLF
+            let line = String::from_utf8_lossy(&buff);
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
 
-        stdout.read_line(&mut buff).expect("Error reading");
-        parse_one(buff.as_str())
+            match parse_one(line) {
+                UciMessage::Unknown(text, _) if !text.starts_with("bestmove") => { debug!("SKIPPING: {}", text); },
+                UciMessage::Info(attrs) if attrs.iter().all(|attr| matches!(attr, UciInfoAttribute::String(_))) => {
+                    debug!("INFO STRING: {}", line);
+                },
+                message => return Ok(message)
+            }
+        }
     }
 
     /// Given a game, and additional moves to consider, and a depth; analyze the game
     /// A Receiver of Analysis structs is returned
     /// When the depth is reached (None for infinite), or the Receiver is dropped,
     /// the engine will stop its analysis
+    /// If a search at least as deep was already finished for the position, the cached lines are returned
     pub fn analyze(&mut self, game :&Game, moves: Vec<ChessMove>, depth :Option<u8>) -> Receiver<Analysis> {
         debug!("CUR POS: {}", game.current_position());
 
-        { // scope our lock
+        // the position actually being searched, after the additional moves
+        let board = moves.iter().fold(game.current_position(), |board, mv| board.make_move_new(*mv));
+
+        if let Some(depth) = depth {
+            if let Some(cached) = self.cache.lock().unwrap().get(&board, self.multi_pv, depth) {
+                debug!("CACHE HIT: depth {} for {}", cached.depth, board);
+
+                // replay the cached search through a channel, just like the engine would
+                let (tx, rx) = channel();
+
+                for line in cached.lines.iter() {
+                    tx.send(Analysis::PossibleMove(line.clone())).unwrap();
+                }
+
+                tx.send(Analysis::BestMove(cached.best_move)).unwrap();
+
+                return rx;
+            }
+        }
+
+        // tell the engine to start processing
+        let go = if depth.is_some() {
+            UciMessage::Go {
+                time_control: None,
+                search_control: Some(UciSearchControl {
+                    search_moves: vec![],
+                    mate: None,
+                    depth,
+                    nodes: None
+                })
+            }
+        } else {
+            UciMessage::Go {
+                time_control: Some(UciTimeControl::Infinite),
+                search_control: None
+            }
+        };
+
+        self.search(game, moves, go, Some(board))
+    }
+
+    /// Searches what the opponent would play if it were their turn, by passing the move to them
+    /// There's no threat to find when the side to move is in check, as passing would be illegal
+    pub fn threat(&mut self, game :&Game, depth :u8) -> Option<Receiver<Analysis>> {
+        // null_move flips the side to move and clears the en passant square
+        let board = game.current_position().null_move()?;
+
+        Some(self.analyze(&Game::new_with_board(board), vec![], Some(depth)))
+    }
+
+    /// Asks the engine for a move to play in the game, limited by both depth and time
+    /// These are never cached, as strength-limited engines purposely vary their moves
+    pub fn play(&mut self, game :&Game, depth :u8, move_time :Duration) -> Receiver<Analysis> {
+        let go = UciMessage::Go {
+            time_control: Some(UciTimeControl::MoveTime(vampirc_uci::Duration::milliseconds(move_time.as_millis() as i64))),
+            search_control: Some(UciSearchControl {
+                search_moves: vec![],
+                mate: None,
+                depth: Some(depth),
+                nodes: None
+            })
+        };
+
+        self.search(game, vec![], go, None)
+    }
+
+    /// Asks the engine for a move with the game on the clock, so it manages its own time
+    /// The depth still limits how strong the move can be
+    pub fn play_on_clock(&mut self, game :&Game, depth :u8, white_time :Duration, black_time :Duration, increment :Duration) -> Receiver<Analysis> {
+        let millis = |time :Duration| Some(vampirc_uci::Duration::milliseconds(time.as_millis() as i64));
+
+        let go = UciMessage::Go {
+            time_control: Some(UciTimeControl::TimeLeft {
+                white_time: millis(white_time),
+                black_time: millis(black_time),
+                white_increment: millis(increment),
+                black_increment: millis(increment),
+                moves_to_go: None
+            }),
+            search_control: Some(UciSearchControl {
+                search_moves: vec![],
+                mate: None,
+                depth: Some(depth),
+                nodes: None
+            })
+        };
+
+        self.search(game, vec![], go, None)
+    }
+
+    /// Sends the position and go message, returning the Receiver for the search
+    /// A search already running is stopped first, so each search's reader only sees its own messages
+    /// When `cache_board` is set, the finished search is saved in the cache for that board
+    fn search(&mut self, game :&Game, moves: Vec<ChessMove>, go :UciMessage, cache_board :Option<Board>) -> Receiver<Analysis> {
+        { // scope our locks
+            let mut searching = self.idle();
+            *searching = true;
+
             let mut stdin = self.stdin.lock().unwrap();
 
             // set the position
@@ -154,40 +361,39 @@ impl Uci {
                 moves
             });
 
-            // tell the engine to start processing
-            if depth.is_some() {
-                Self::send_msg(&mut stdin, UciMessage::Go {
-                    time_control: None,
-                    search_control: Some(UciSearchControl {
-                        search_moves: vec![],
-                        mate: None,
-                        depth,
-                        nodes: None
-                    })
-                });
-            } else {
-                Self::send_msg(&mut stdin, UciMessage::Go {
-                    time_control: Some(UciTimeControl::Infinite),
-                    search_control: None
-                });
-            }
+            Self::send_msg(&mut stdin, go);
         }
 
         // clone STDIN & STDOUT
         let stdin_clone = self.stdin.clone();
         let stdout_clone = self.stdout.clone();
+        let cache_clone = self.cache.clone();
+        let searching_clone = self.searching.clone();
+        let multi_pv = self.multi_pv;
 
         // create a channel for sending back the analysis
         let (tx, rx) = channel();
 
         // spawn a thread to read the messages from the engine
         thread::spawn(move || {
+            // the latest line for each MultiPV, saved to the cache when the search finishes
+            let mut lines = HashMap::new();
+
             // read everything it sent back
             loop {
                 let message = {
                     let mut stdout = stdout_clone.lock().unwrap();
 
-                    Self::recv_msg(&mut stdout)
+                    Self::recv_msg(&mut *stdout)
+                };
+
+                // the engine went away, so dropping the Sender ends the search
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        error!("Error reading from engine: {:?}", e);
+                        break
+                    }
                 };
 
                 // debug!("MSG: {:?}", message);
@@ -206,11 +412,18 @@ impl Uci {
                         for attr in attrs {
                             match attr {
                                 UciInfoAttribute::Depth(d) => { possible_move.depth = d; },
-                                UciInfoAttribute::Score { cp, mate, .. } => { if let Some(score) = cp { possible_move.score = score; } },
+                                UciInfoAttribute::Score { cp, mate, .. } => {
+                                    if let Some(score) = cp {
+                                        possible_move.score = score;
+                                    } else if let Some(mate) = mate {
+                                        possible_move.score = if mate > 0 { MATE_SCORE - mate as i32 } else { -MATE_SCORE - mate as i32 };
+                                    }
+                                },
                                 UciInfoAttribute::Pv(moves) => { possible_move.moves = moves; }
                                 UciInfoAttribute::MultiPv(multi_pv) => { possible_move.multi_pv = multi_pv; }
+                                UciInfoAttribute::Nodes(nodes) => { possible_move.nodes = nodes; }
                                 // UciInfoAttribute::CurrMove(chess_move) => { info.push_str(&chess_move.to_string()); },
-                                UciInfoAttribute::String(s) => { eprintln!("STR: {}", s); }
+                                UciInfoAttribute::String(s) => { debug!("INFO STRING: {}", s); }
                                 _ => ()
                             }
                         }
@@ -225,11 +438,30 @@ impl Uci {
                     UciMessage::BestMove { best_move, ponder } => {
                         Analysis::BestMove(best_move)
                     }
+                    // there's no move to send back, but the search is over
+                    UciMessage::Unknown(text, _) => {
+                        debug!("NO BEST MOVE: {}", text);
+                        break
+                    }
                     _ => {
-                        panic!("Unexpected message: {:?}", message)
+                        warn!("Unexpected message: {:?}", message);
+                        continue
                     }
                 };
 
+                match &analysis {
+                    Analysis::PossibleMove(pm) if !pm.moves.is_empty() => { lines.insert(pm.multi_pv, pm.clone()); },
+                    Analysis::BestMove(best_move) => {
+                        if let Some(board) = cache_board.as_ref() {
+                            let lines = lines.drain().map(|(_mpv, pm)| pm).sorted_by_key(|pm| pm.multi_pv).collect_vec();
+                            let depth = lines.iter().map(|pm| pm.depth).min().unwrap_or(0);
+
+                            cache_clone.lock().unwrap().insert(board, multi_pv, CachedAnalysis { depth, lines, best_move: *best_move });
+                        }
+                    },
+                    _ => ()
+                }
+
                 let break_loop = if let Analysis::BestMove(_) = analysis { true } else { false };
 
                 // send the analysis, check for disconnected receiver
@@ -246,6 +478,11 @@ impl Uci {
                     break
                 }
             }
+
+            // however the search ended, the next one can start
+            let (lock, finished) = &*searching_clone;
+            *lock.lock().unwrap() = false;
+            finished.notify_all();
         });
 
         // return the receiver side of the channel
@@ -262,8 +499,9 @@ impl Uci {
         let mut best_moves = HashMap::new();
 
         for analysis in rx {
-            if let Analysis::PossibleMove(pm) = analysis {
-                best_moves.insert(pm.multi_pv, pm);
+            match analysis {
+                Analysis::PossibleMove(pm) if !pm.moves.is_empty() => { best_moves.insert(pm.multi_pv, pm); },
+                _ => ()
             }
         }
 
@@ -278,6 +516,11 @@ impl Uci {
         debug!("BEST MOVES");
         best_moves.iter().for_each(|(score, mv)| debug!("{}: {}", score, mv));
 
+        // a search that was stopped might not have sent any lines
+        if best_moves.is_empty() {
+            return (false, vec![])
+        }
+
         // check to see if this move is one of the "best" moves, if it is, then it's not a blunder
         if best_moves.iter().any(|(score, mv)| *mv == proposed_move) {
             return (false, best_moves)
@@ -288,8 +531,9 @@ impl Uci {
         let mut best_responses = HashMap::new();
 
         for analysis in rx {
-            if let Analysis::PossibleMove(pm) = analysis {
-                best_responses.insert(pm.multi_pv, pm);
+            match analysis {
+                Analysis::PossibleMove(pm) if !pm.moves.is_empty() => { best_responses.insert(pm.multi_pv, pm); },
+                _ => ()
             }
         }
 
@@ -304,6 +548,10 @@ impl Uci {
         debug!("BEST RESPONSES");
         best_responses.iter().for_each(|(score, mv)| debug!("{}: {}", score, mv));
 
+        if best_responses.is_empty() {
+            return (false, vec![])
+        }
+
         // compute the diff from the best move to the best response, if it's more than a 300 points swing, that's a blunder
         let diff = (best_responses[0].0 - best_moves[0].0).abs();
 
@@ -324,7 +572,10 @@ mod uci_tests {
     use std::convert::TryFrom;
     use std::str::FromStr;
 
+    use std::io::{self, Cursor};
+
     use chess::{Game, ChessMove, Square};
+    use vampirc_uci::{UciMessage, UciInfoAttribute};
     use crate::uci::{Uci, Analysis};
     use simple_logger::SimpleLogger;
     use std::time::Duration;
@@ -350,6 +601,43 @@ mod uci_tests {
         let uci = Uci::start_engine(&mut cmd);
     }
 
+    #[test]
+    fn recv_msg_skips_noise() {
+        let output = "Ethereal 12.75 by Andrew Grant\r\n\r\n\nid name Ethereal 12.75\r\nnot a command\ninfo string NNUE evaluation enabled\r\nuciok\r\nreadyok";
+        let mut reader = Cursor::new(output.as_bytes());
+
+        assert_eq!(UciMessage::Id { name: Some("Ethereal 12.75".to_string()), author: None }, Uci::recv_msg(&mut reader).unwrap());
+        assert_eq!(UciMessage::UciOk, Uci::recv_msg(&mut reader).unwrap());
+
+        // a last line without a newline is still read
+        assert_eq!(UciMessage::ReadyOk, Uci::recv_msg(&mut reader).unwrap());
+
+        // then the engine is gone
+        assert_eq!(io::ErrorKind::UnexpectedEof, Uci::recv_msg(&mut reader).unwrap_err().kind());
+    }
+
+    #[test]
+    fn recv_msg_keeps_info() {
+        let output = "info depth 3 score cp 20 multipv 1 pv e2e4 e7e5\r\nbestmove e2e4 ponder e7e5\r\nbestmove (none)\r\n";
+        let mut reader = Cursor::new(output.as_bytes());
+
+        match Uci::recv_msg(&mut reader).unwrap() {
+            UciMessage::Info(attrs) => assert!(attrs.contains(&UciInfoAttribute::Depth(3))),
+            message => panic!("Expected info, got {:?}", message)
+        }
+
+        match Uci::recv_msg(&mut reader).unwrap() {
+            UciMessage::BestMove { best_move, .. } => assert_eq!(ChessMove::new(Square::E2, Square::E4, None), best_move),
+            message => panic!("Expected bestmove, got {:?}", message)
+        }
+
+        // no legal moves, but the search still ended
+        match Uci::recv_msg(&mut reader).unwrap() {
+            UciMessage::Unknown(text, _) => assert_eq!("bestmove (none)", text),
+            message => panic!("Expected an empty bestmove, got {:?}", message)
+        }
+    }
+
     #[test]
     fn analyze_test() {
         SimpleLogger::new().init().unwrap();
@@ -374,6 +662,24 @@ mod uci_tests {
         }
     }
 
+    #[test]
+    fn threat_test() {
+        let mut cmd = Command::new("/usr/games/stockfish");
+        let mut uci = Uci::start_engine(&mut cmd);
+
+        // black to move, but white is threatening Qxf7#
+        let game = Game::from_str("r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3").expect("Error creating game");
+        let threat = uci.threat(&game, 5).expect("No threat").iter().find_map(|analysis| {
+            if let Analysis::BestMove(mv) = analysis { Some(mv) } else { None }
+        });
+
+        assert_eq!(Some(ChessMove::new(Square::H5, Square::F7, None)), threat);
+
+        // no passing while in check
+        let game = Game::from_str("rnbqkbnr/ppppp2p/5p2/6pQ/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 3").expect("Error creating game");
+        assert!(uci.threat(&game, 5).is_none());
+    }
+
     #[test]
     fn check_for_blunder_true_test() {
         SimpleLogger::new().init().unwrap();